@@ -1,6 +1,7 @@
 use std::io::Read;
 use utils::try;
 use toml::Parser;
+use toml::Value;
 use toml::Value::{
 	String,
 	Array
@@ -9,15 +10,15 @@ use toml::Value::{
 #[cfg(test)]
 mod tests;
 
-pub fn load_config(file: &mut Read)->Result<Vec<String>, String> {
-	let cnt = {
-		let mut cnt = String::new();
-		try_or_str!(file.read_to_string(&mut cnt));
-		try(cnt)
-	};
-	let tbl = cnt.try(|x| Parser::new(&x).parse());
-	tbl.try_or_err(|x| match x.get("urls") {
-		Some(&Array(ref arr)) =>{
+pub struct Config {
+	pub urls: Vec<String>,
+	pub targets: Vec<String>,
+	pub defines: Vec<String>
+}
+
+fn strings(v: &Value)->Option<Vec<String>> {
+	match v {
+		&Array(ref arr) =>{
 			let mut ret = Vec::new();
 			for item in arr {
 				if let &String(ref x) = item {
@@ -29,5 +30,35 @@ pub fn load_config(file: &mut Read)->Result<Vec<String>, String> {
 			Some(ret)
 		},
 		_ =>None
+	}
+}
+
+pub fn load_config(file: &mut Read)->Result<Config, String> {
+	let cnt = {
+		let mut cnt = String::new();
+		try_or_str!(file.read_to_string(&mut cnt));
+		try(cnt)
+	};
+	let tbl = cnt.try(|x| Parser::new(&x).parse());
+	tbl.try_or_err(|x| {
+		let urls = match x.get("urls").and_then(strings) {
+			Some(x) =>x,
+			None =>return None
+		};
+		let targets = match x.get("targets") {
+			Some(v) =>match strings(v) {
+				Some(x) =>x,
+				None =>return None
+			},
+			None =>Vec::new()
+		};
+		let defines = match x.get("defines") {
+			Some(v) =>match strings(v) {
+				Some(x) =>x,
+				None =>return None
+			},
+			None =>Vec::new()
+		};
+		Some(Config { urls: urls, targets: targets, defines: defines })
 	}, "illegal format".to_string())
 }
@@ -1,6 +1,21 @@
 #[test]
 fn test() {
 	let mut input = b"urls = [ \"http://example.com/\", \"http://g.cn/\" ]";
-	let urls = super::load_config(&mut input).unwrap();
-	assert_eq!(urls, vec![ "http://example.com/", "http://g.cn/" ])
+	let config = super::load_config(&mut input).unwrap();
+	assert_eq!(config.urls, vec![ "http://example.com/", "http://g.cn/" ]);
+	assert!(config.targets.is_empty())
+}
+
+#[test]
+fn test_targets() {
+	let mut input = b"urls = []\ntargets = [ \"x86\", \"x64\" ]";
+	let config = super::load_config(&mut input).unwrap();
+	assert_eq!(config.targets, vec![ "x86", "x64" ])
+}
+
+#[test]
+fn test_defines() {
+	let mut input = b"urls = []\ndefines = [ \"NTDDI_VERSION=0x06010000\", \"UNICODE\" ]";
+	let config = super::load_config(&mut input).unwrap();
+	assert_eq!(config.defines, vec![ "NTDDI_VERSION=0x06010000", "UNICODE" ])
 }
@@ -0,0 +1,95 @@
+use prs::{
+	GlobalNameSpace,
+	Type,
+	Layout,
+	Field
+};
+use target::Target;
+
+#[cfg(test)]
+mod tests;
+
+fn layout(value: &Type)->Option<&Layout> {
+	match value {
+		&Type::Struct(ref s) =>Some(s.layout()),
+		&Type::Union(ref u) =>Some(u.layout()),
+		_ =>None
+	}
+}
+
+fn span(field: Option<&Field>)->String {
+	match field {
+		Some(field) if field.size == 0 =>format!("{:02X}", field.offset),
+		Some(field) =>format!("{:02X} - {:02X}", field.offset, field.offset + field.size),
+		None =>format!("-")
+	}
+}
+
+fn compare(lhs: (&Type, &Target), rhs: (&Type, &Target))->Vec<String> {
+	let mut ret = Vec::new();
+	let (lsize, rsize) = (lhs.0.size(lhs.1), rhs.0.size(rhs.1));
+	if lsize != rsize {
+		ret.push(format!("\t{:32} {:12} {:02X}\n", "(size)", format!("{:02X}", lsize), rsize))
+	}
+	let (llayout, rlayout) = (layout(lhs.0).unwrap(), layout(rhs.0).unwrap());
+	for field in llayout.iter().filter(|x| !x.name.is_empty()) {
+		let other = rlayout.get(&field.name);
+		if other.map(|x| (x.offset, x.size)) != Some((field.offset, field.size)) {
+			ret.push(format!("\t{:32} {:12} {}\n", field.name, span(Some(field)), span(other)))
+		}
+	}
+	for field in rlayout.iter().filter(|x| !x.name.is_empty()) {
+		if !llayout.contains_key(&field.name) {
+			ret.push(format!("\t{:32} {:12} {}\n", field.name, span(None), span(Some(field))))
+		}
+	}
+	ret
+}
+
+pub fn report(lhs: &GlobalNameSpace, lhs_target: &Target, rhs: &GlobalNameSpace, rhs_target: &Target)->String {
+	let mut names = Vec::new();
+	for (name, _) in lhs.iter().chain(rhs.iter()).filter(|&(_, v)| layout(v).is_some()) {
+		if !names.contains(name) {
+			names.push(name.clone())
+		}
+	}
+	names.sort_by(|a, b| (format!("{}", a), format!("{:?}", a)).cmp(&(format!("{}", b), format!("{:?}", b))));
+	let mut groups: Vec<(Vec<String>, Option<&Type>, Option<&Type>)> = Vec::new();
+	for name in names.iter() {
+		let (lvalue, rvalue) = (lhs.get(name).and_then(|x| layout(x).map(|_| x)), rhs.get(name).and_then(|x| layout(x).map(|_| x)));
+		match groups.iter().position(|&(_, l, r)| l == lvalue && r == rvalue) {
+			Some(idx) =>groups[idx].0.push(format!("{}", name)),
+			None =>groups.push((vec![format!("{}", name)], lvalue, rvalue))
+		}
+	}
+	let mut ret = String::new();
+	for (names, lvalue, rvalue) in groups.into_iter() {
+		let names = names.iter().fold(String::new(), |acc, x| if acc.is_empty() {
+			x.clone()
+		} else {
+			acc + ", " + x
+		});
+		let lines = match (lvalue, rvalue) {
+			(Some(lvalue), Some(rvalue)) =>compare((lvalue, lhs_target), (rvalue, rhs_target)),
+			(Some(_), None) =>{
+				ret.push_str(&format!("[{}] only defined for {}\n", names, lhs_target.name));
+				continue
+			},
+			_ =>{
+				ret.push_str(&format!("[{}] only defined for {}\n", names, rhs_target.name));
+				continue
+			}
+		};
+		if !lines.is_empty() {
+			ret.push_str(&format!("{:33} {:12} {}\n", format!("[{}]", names), lhs_target.name, rhs_target.name));
+			for line in lines.iter() {
+				ret.push_str(line)
+			}
+		}
+	}
+	if ret.is_empty() {
+		format!("no layout differences between {} and {}\n", lhs_target.name, rhs_target.name)
+	} else {
+		ret
+	}
+}
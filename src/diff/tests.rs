@@ -0,0 +1,37 @@
+#[test]
+fn test_report() {
+	use prs::compile;
+	use target;
+	use super::report;
+	let code = "typedef struct _s { DWORD a; PVOID p; WORD w; } s;";
+	let (x86, x64) = (target::lookup("x86").unwrap(), target::lookup("x64").unwrap());
+	let lhs = compile(&mut code.chars(), &[], x86).unwrap();
+	let rhs = compile(&mut code.chars(), &[], x64).unwrap();
+	let header = format!("{:33} {:12} {}\n", "[_s, s]", "x86", "x64");
+	assert_eq!(report(&lhs, x86, &rhs, x64), format!("{}\t{:32} {:12} {}\n\t{:32} {:12} {}\n\t{:32} {:12} {}\n",
+		header, "(size)", "0C", "18", "p", "04 - 08", "08 - 10", "w", "08 - 0A", "10 - 12"));
+	assert_eq!(report(&lhs, x86, &lhs, x86), "no layout differences between x86 and x86\n")
+}
+
+#[test]
+fn test_only_defined() {
+	use prs::compile;
+	use target;
+	use super::report;
+	let (x86, x64) = (target::lookup("x86").unwrap(), target::lookup("x64").unwrap());
+	let lhs = compile(&mut "struct a { DWORD d; };".chars(), &[], x86).unwrap();
+	let rhs = compile(&mut "struct b { DWORD d; };".chars(), &[], x64).unwrap();
+	assert_eq!(report(&lhs, x86, &rhs, x64), "[a] only defined for x86\n[b] only defined for x64\n")
+}
+
+#[test]
+fn test_same_display_name() {
+	use prs::compile;
+	use target;
+	use super::report;
+	let (x86, x64) = (target::lookup("x86").unwrap(), target::lookup("x64").unwrap());
+	let code = "typedef struct a { PVOID p; } a;";
+	let lhs = compile(&mut code.chars(), &[], x86).unwrap();
+	let rhs = compile(&mut code.chars(), &[], x64).unwrap();
+	assert!(report(&lhs, x86, &rhs, x64).starts_with(&format!("{:33} {:12} {}\n", "[a, a]", "x86", "x64")))
+}
@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use sdk;
+use pre::Macros;
+use prs::{
+	GlobalNameSpace,
+	TypeName,
+	Type,
+	Enum,
+	FieldType,
+	Function,
+	Item,
+	evaluate
+};
+
+#[cfg(test)]
+mod tests;
+
+const KEYWORDS: [&'static str; 38] = [
+	"abstract", "as", "box", "break", "const", "continue", "crate", "do", "else", "enum",
+	"extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop", "match",
+	"mod", "move", "mut", "priv", "pub", "ref", "return", "self", "static", "struct",
+	"super", "trait", "true", "type", "unsafe", "use", "while"
+];
+
+fn ident(name: &str)->String {
+	if KEYWORDS.iter().any(|&k| k == name) {
+		format!("{}_", name)
+	} else {
+		name.to_string()
+	}
+}
+
+fn sized_type(size: usize)->String {
+	match size {
+		1 =>format!("u8"),
+		2 =>format!("u16"),
+		4 =>format!("u32"),
+		8 =>format!("u64"),
+		n =>format!("[u8; {}]", n)
+	}
+}
+
+fn abi(f: &Function)->&'static str {
+	match f.convention {
+		Some(ref conv) if conv == "__cdecl" || conv == "CDECL" || conv == "WINAPIV" =>"C",
+		_ =>"system"
+	}
+}
+
+fn signature(f: &Function, named: bool, ns: &GlobalNameSpace, errors: &mut Vec<String>)->Result<String, String> {
+	let mut params = String::new();
+	for &(ref name, ref ty) in f.params.iter() {
+		let ty = try!(rust_type(ty, 0, ns, errors));
+		let param = match (named, name.is_empty()) {
+			(false, _) =>ty,
+			(true, true) =>format!("_: {}", ty),
+			(true, false) =>format!("{}: {}", ident(name), ty)
+		};
+		params = if params.is_empty() { param } else { params + ", " + &param };
+	}
+	match f.ret {
+		FieldType::Primitive(ref name) if name == "VOID" =>Ok(format!("({})", params)),
+		ref ret =>Ok(format!("({}) -> {}", params, try!(rust_type(ret, 0, ns, errors))))
+	}
+}
+
+fn fn_type(f: &Function, ns: &GlobalNameSpace, errors: &mut Vec<String>)->Result<String, String> {
+	Ok(format!("unsafe extern \"{}\" fn{}", abi(f), try!(signature(f, false, ns, errors))))
+}
+
+fn gen_externs(functions: Vec<(&String, &Function)>, ns: &GlobalNameSpace, errors: &mut Vec<String>)->Vec<String> {
+	let mut blocks: Vec<(Option<String>, &'static str, String)> = Vec::new();
+	for (name, f) in functions.into_iter() {
+		let decl = match signature(f, true, ns, errors) {
+			Ok(x) =>format!("\tpub fn {}{};\n", ident(name), x),
+			Err(e) =>{
+				errors.push(format!("{} in {}", e, name));
+				continue
+			}
+		};
+		match blocks.iter().position(|&(ref lib, abi_, _)| *lib == f.library && abi_ == abi(f)) {
+			Some(idx) =>blocks[idx].2.push_str(&decl),
+			None =>blocks.push((f.library.clone(), abi(f), decl))
+		}
+	}
+	blocks.into_iter().map(|(lib, abi_, decls)| {
+		let link = lib.map_or(String::new(), |x| format!("#[link(name = \"{}\")]\n", x));
+		format!("{}extern \"{}\" {{\n{}}}\n", link, abi_, decls)
+	}).collect()
+}
+
+// pointers to functions are nullable function types in Rust
+fn is_function(ty: &FieldType, ns: &GlobalNameSpace)->bool {
+	match ty {
+		&FieldType::Function(_) =>true,
+		&FieldType::Qualified(ref ty, _) =>is_function(ty, ns),
+		&FieldType::Named(ref name) =>match ns.get(name) {
+			Some(&Type::Function(_)) =>true,
+			_ =>false
+		},
+		_ =>false
+	}
+}
+
+// `errors` collects notes on pointers whose pointee could not be resolved and became `c_void`
+fn rust_type(ty: &FieldType, size: usize, ns: &GlobalNameSpace, errors: &mut Vec<String>)->Result<String, String> {
+	match ty {
+		&FieldType::Primitive(ref name) =>Ok(match sdk::lookup(name) {
+			Some(base) =>base.rust.to_string(),
+			None =>sized_type(size)
+		}),
+		&FieldType::Named(ref name) =>match (ns.get(name), name) {
+			(Some(&Type::Struct(_)), _) | (Some(&Type::Union(_)), _) | (Some(&Type::Enum(_)), _) =>{
+				Ok(ident(&format!("{}", name)))
+			},
+			(Some(&Type::Pointer(ref inner)), _) =>rust_type(&FieldType::Pointer(Box::new(inner.clone())), 0, ns, errors),
+			(Some(&Type::Function(ref f)), _) =>fn_type(f, ns, errors),
+			(Some(&Type::Primitive(ref base)), _) =>rust_type(&FieldType::Primitive(base.clone()), size, ns, errors),
+			(_, &TypeName::Enum(_)) =>Ok(format!("i32")),
+			_ =>Err(format!("type {} is unknown", name))
+		},
+		&FieldType::Pointer(ref ty) =>{
+			let mutability = if ty.is_const() { "const" } else { "mut" };
+			Ok(match rust_type(ty, 0, ns, errors) {
+				Ok(x) =>if is_function(ty, ns) { format!("Option<{}>", x) } else { format!("*{} {}", mutability, x) },
+				Err(e) =>{
+					errors.push(format!("{}, pointing to c_void instead", e));
+					format!("*{} ::std::os::raw::c_void", mutability)
+				}
+			})
+		},
+		&FieldType::Qualified(ref ty, _) =>rust_type(ty, size, ns, errors),
+		&FieldType::Function(ref f) =>fn_type(f, ns, errors),
+		&FieldType::Array(ref ty, cnt) =>Ok(format!("[{}; {}]", try!(rust_type(ty, size / cnt, ns, errors)), cnt)),
+		&FieldType::AnonymousStruct | &FieldType::AnonymousUnion =>Ok(sized_type(size))
+	}
+}
+
+const ATTRIBUTES: &'static str = "#[derive(Copy, Clone)]\n#[allow(non_camel_case_types, non_snake_case)]\n";
+
+fn gen_aggregate(name: &str, value: &Type, ns: &GlobalNameSpace, out: &mut Vec<String>, errors: &mut Vec<String>)
+	->Result<(), String> {
+	let (keyword, items, pack, declared) = match value {
+		&Type::Struct(ref s) =>("struct", s.items(), s.pack(), s.declared_align()),
+		&Type::Union(ref u) =>("union", u.items(), u.pack(), u.declared_align()),
+		_ =>return Err(format!("{} is not a struct or union", name))
+	};
+	let repr = match (pack, declared) {
+		(Some(_), Some(_)) =>return Err(format!("{} is both packed and explicitly aligned", name)),
+		(Some(pack), None) =>format!("C, packed({})", pack),
+		(None, Some(align)) =>format!("C, align({})", align),
+		(None, None) =>format!("C")
+	};
+	let mut fields = Vec::new();
+	let mut accessors = Vec::new();
+	let mut flexible = None;
+	for item in items.into_iter() {
+		match item {
+			Item::Nested(member) =>{
+				let type_name = member.type_name(name);
+				try!(gen_aggregate(&type_name, &member.value, ns, out, errors));
+				fields.push((member.offset, member.size(), ident(&member.name), ident(&type_name), Vec::new()))
+			},
+			Item::Field(field) if field.bits.is_some() =>{
+				let (bit, width) = field.bits.unwrap();
+				if bit == 0 {
+					let backing = format!("_bitfield{}", fields.iter().filter(|x| x.2.starts_with("_bitfield")).count());
+					fields.push((field.offset, field.size, backing, sized_type(field.size), Vec::new()))
+				}
+				if width > 0 && !field.name.is_empty() {
+					let backing = fields.last().unwrap().2.clone();
+					accessors.push((field.name.clone(), backing, sized_type(field.size), bit, width, field.comments.clone()))
+				}
+			},
+			Item::Field(field) if field.flexible =>{
+				let (elem, cnt) = match field.ty {
+					FieldType::Array(ref ty, cnt) =>(try!(rust_type(ty, if cnt == 0 { 0 } else { field.size / cnt }, ns, errors)), cnt),
+					_ =>return Err(format!("flexible field {} in {} is not an array", field.name, name))
+				};
+				fields.push((field.offset, field.size, ident(&field.name), format!("[{}; {}]", elem, cnt), field.comments.clone()));
+				flexible = Some((field.name.clone(), elem, field.offset))
+			},
+			Item::Field(field) =>{
+				if field.size == 0 {
+					return Err(format!("size of field {} in {} is unknown", field.name, name))
+				}
+				let ty = try!(rust_type(&field.ty, field.size, ns, errors));
+				fields.push((field.offset, field.size, ident(&field.name), ty, field.comments.clone()))
+			}
+		}
+	}
+	let mut ret = format!("#[repr({})]\n{}pub {} {} {{\n", repr, ATTRIBUTES, keyword, ident(name));
+	let mut bound = 0;
+	let mut padding = 0;
+	for (offset, size, field, ty, comments) in fields.into_iter() {
+		if keyword == "union" {
+			if offset != 0 {
+				return Err(format!("field {} in union {} is not at offset 0", field, name))
+			}
+		} else {
+			if offset < bound {
+				return Err(format!("field {} in {} overlaps with previous field", field, name))
+			}
+			if offset > bound {
+				ret.push_str(&format!("\t_padding{}: [u8; {}],\n", padding, offset - bound));
+				padding += 1
+			}
+			bound = offset + size
+		}
+		for comment in comments.iter() {
+			ret.push_str(&format!("\t/// {}\n", comment))
+		}
+		ret.push_str(&format!("\tpub {}: {},\n", field, ty));
+	}
+	ret.push_str("}\n");
+	let mut methods = Vec::new();
+	let wrap = |body: String| if keyword == "union" { format!("unsafe {{ {} }}", body) } else { body };
+	for (field, backing, ty, bit, width, comments) in accessors.into_iter() {
+		let mask = if width >= 64 { !0u64 } else { (1u64 << width) - 1 };
+		let mut method = String::new();
+		for comment in comments.iter() {
+			method.push_str(&format!("\t/// {}\n", comment))
+		}
+		method.push_str(&format!("\tpub fn {}(&self)->{} {{\n\t\t{}\n\t}}\n", ident(&field), ty,
+			wrap(format!("(self.{} >> {}) & {:#x}", backing, bit, mask))));
+		method.push_str(&format!("\tpub fn set_{}(&mut self, value: {}) {{\n\t\t{}\n\t}}\n", field, ty,
+			wrap(format!("self.{0} = (self.{0} & !({1:#x} << {2})) | ((value & {1:#x}) << {2})", backing, mask, bit))));
+		methods.push(method)
+	}
+	if let Some((field, elem, offset)) = flexible {
+		let field = ident(&field);
+		methods.push(format!("\t/// Bytes needed to hold `count` elements of `{0}`.\n\
+			\tpub fn alloc_size(count: usize)->usize {{\n\
+			\t\t::std::cmp::max(::std::mem::size_of::<Self>(), {1} + count * ::std::mem::size_of::<{2}>())\n\t}}\n\
+			\t/// `this` must point to an allocation of at least `alloc_size(count)` bytes.\n\
+			\tpub unsafe fn {0}<'a>(this: *const Self, count: usize)->&'a [{2}] {{\n\
+			\t\t::std::slice::from_raw_parts((this as *const u8).offset({1}) as *const {2}, count)\n\t}}\n\
+			\t/// `this` must point to an allocation of at least `alloc_size(count)` bytes.\n\
+			\tpub unsafe fn {0}_mut<'a>(this: *mut Self, count: usize)->&'a mut [{2}] {{\n\
+			\t\t::std::slice::from_raw_parts_mut((this as *mut u8).offset({1}) as *mut {2}, count)\n\t}}\n", field, offset, elem))
+	}
+	if !methods.is_empty() {
+		ret.push_str(&format!("\nimpl {} {{\n", ident(name)));
+		ret.push_str(&methods.into_iter().fold(String::new(), |acc, x| if acc.is_empty() { x } else { acc + "\n" + &x }));
+		ret.push_str("}\n")
+	}
+	out.push(ret);
+	Ok(())
+}
+
+fn gen_enum(name: &str, value: &Enum)->String {
+	let name = ident(name);
+	let mut ret = format!("#[repr(C)]\n#[derive(Copy, Clone, PartialEq, Eq, Debug)]\n#[allow(non_camel_case_types)]\n\
+		pub struct {}(pub i32);\n", name);
+	if value.iter().next().is_some() {
+		ret.push_str(&format!("\nimpl {} {{\n", name));
+		for &(ref item, x) in value.iter() {
+			ret.push_str(&format!("\tpub const {}: {} = {}({});\n", ident(item), name, name, x as i32))
+		}
+		ret.push_str("}\n")
+	}
+	ret
+}
+
+pub fn generate(ns: &GlobalNameSpace)->(String, Vec<String>) {
+	let mut entries = ns.iter().collect::<Vec<_>>();
+	entries.sort_by(|a, b| format!("{}", a.0).cmp(&format!("{}", b.0)));
+	let mut seen: HashMap<String, &Type> = HashMap::new();
+	// identical aggregates are defined once, preferably under their tag, other names become aliases
+	let mut defs: Vec<(String, &Type, bool)> = Vec::new();
+	let mut aliases = Vec::new();
+	let mut functions = Vec::new();
+	let mut errors = Vec::new();
+	for (k, v) in entries.into_iter() {
+		let (name, tagged) = match (k, v) {
+			(&TypeName::Function(ref name), &Type::Function(ref f)) =>{
+				functions.push((name, f));
+				continue
+			},
+			(&TypeName::Normal(ref name), &Type::Pointer(ref ty)) =>{
+				match rust_type(&FieldType::Pointer(Box::new(ty.clone())), 0, ns, &mut errors) {
+					Ok(x) =>aliases.push((name.clone(), x)),
+					Err(e) =>errors.push(e)
+				}
+				continue
+			},
+			(&TypeName::Normal(ref name), &Type::Primitive(ref base)) =>{
+				match rust_type(&FieldType::Primitive(base.clone()), 0, ns, &mut errors) {
+					Ok(x) =>aliases.push((name.clone(), x)),
+					Err(e) =>errors.push(e)
+				}
+				continue
+			},
+			(&TypeName::Struct(ref name), &Type::Struct(_)) |
+			(&TypeName::Union(ref name), &Type::Union(_)) |
+			(&TypeName::Enum(ref name), &Type::Enum(_)) =>(name, true),
+			(&TypeName::Normal(ref name), &Type::Struct(_)) |
+			(&TypeName::Normal(ref name), &Type::Union(_)) |
+			(&TypeName::Normal(ref name), &Type::Enum(_)) =>(name, false),
+			_ =>continue
+		};
+		if let Some(&old) = seen.get(name) {
+			if old != v {
+				errors.push(format!("conflict definition of {}", name))
+			}
+			continue
+		}
+		seen.insert(name.clone(), v);
+		match defs.iter().position(|x| x.1 == v) {
+			Some(idx) if tagged && !defs[idx].2 =>{
+				aliases.push((defs[idx].0.clone(), ident(name)));
+				defs[idx] = (name.clone(), v, true)
+			},
+			Some(idx) =>aliases.push((name.clone(), ident(&defs[idx].0))),
+			None =>defs.push((name.clone(), v, tagged))
+		}
+	}
+	defs.sort_by(|a, b| a.0.cmp(&b.0));
+	let mut items = Vec::new();
+	for (name, value, _) in defs.into_iter() {
+		let mut out = Vec::new();
+		if let &Type::Enum(ref value) = value {
+			items.push(gen_enum(&name, value));
+			continue
+		}
+		match gen_aggregate(&name, value, ns, &mut out, &mut errors) {
+			Ok(()) =>items.extend(out.into_iter()),
+			Err(e) =>errors.push(e)
+		}
+	}
+	// `typedef DWORD u32;` already names the Rust type, an alias would refer to itself
+	aliases.retain(|&(ref name, ref ty)| ident(name) != *ty);
+	aliases.sort();
+	if !aliases.is_empty() {
+		items.push(aliases.into_iter().fold(String::new(), |acc, (name, ty)| {
+			acc + &format!("#[allow(non_camel_case_types)]\npub type {} = {};\n", ident(&name), ty)
+		}))
+	}
+	functions.sort_by(|a, b| a.0.cmp(b.0));
+	items.extend(gen_externs(functions, ns, &mut errors).into_iter());
+	(items.into_iter().fold(String::new(), |acc, code| {
+		if acc.is_empty() { code } else { acc + "\n" + &code }
+	}), errors)
+}
+
+pub fn constants(macros: &Macros)->String {
+	let mut ret = String::new();
+	for value in macros.iter() {
+		if value.predefined || value.params.is_some() || value.body.is_empty() {
+			continue
+		}
+		let body = match macros.expand(&value.body) {
+			Ok(x) =>x,
+			Err(_) =>continue
+		};
+		if let Ok(x) = evaluate(&mut body.chars()) {
+			ret.push_str(&format!("pub const {}: u64 = {};\n", ident(&value.name), x))
+		}
+	}
+	ret
+}
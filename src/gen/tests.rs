@@ -0,0 +1,264 @@
+#[test]
+fn test_generate_struct() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let ns = compile(&mut "typedef struct _s { DWORD val; WORD type; BYTE b; } s, *ps;".chars(), &[], target::default()).unwrap();
+	let body = "{\n\tpub val: u32,\n\tpub type_: u16,\n\tpub b: u8,\n}\n";
+	let attrs = "#[repr(C)]\n#[derive(Copy, Clone)]\n#[allow(non_camel_case_types, non_snake_case)]\n";
+	let aliases = "#[allow(non_camel_case_types)]\npub type ps = *mut _s;\n#[allow(non_camel_case_types)]\npub type s = _s;\n";
+	assert_eq!(generate(&ns).0, format!("{}pub struct _s {}\n{}", attrs, body, aliases))
+}
+
+#[test]
+fn test_unknown_size() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let ns = compile(&mut "struct s { DWORD val; HEADER h; };".chars(), &[], target::default()).unwrap();
+	assert_eq!(generate(&ns), (String::new(), vec![format!("size of field h in s is unknown")]))
+}
+
+#[test]
+fn test_anonymous_union() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let ns = compile(&mut "struct s { BYTE b; union { DWORD val; WORD word; }; };".chars(), &[], target::default()).unwrap();
+	let attrs = "#[repr(C)]\n#[derive(Copy, Clone)]\n#[allow(non_camel_case_types, non_snake_case)]\n";
+	assert_eq!(generate(&ns).0, format!(
+		"{}pub union s_u {{\n\tpub val: u32,\n\tpub word: u16,\n}}\n\n{}pub struct s {{\n\tpub b: u8,\n\t_padding0: [u8; 3],\n\tpub u: s_u,\n}}\n",
+		attrs, attrs))
+}
+
+#[test]
+fn test_nested_names() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let ns = compile(&mut "union v { struct { WORD lo; WORD hi; } DUMMYSTRUCTNAME; DWORD val; };".chars(), &[], target::default()).unwrap();
+	let attrs = "#[repr(C)]\n#[derive(Copy, Clone)]\n#[allow(non_camel_case_types, non_snake_case)]\n";
+	assert_eq!(generate(&ns).0, format!(
+		"{}pub struct v_s {{\n\tpub lo: u16,\n\tpub hi: u16,\n}}\n\n{}pub union v {{\n\tpub s: v_s,\n\tpub val: u32,\n}}\n",
+		attrs, attrs))
+}
+
+#[test]
+fn test_field_types() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let ns = compile(&mut "struct s { struct s *next; BYTE *data; };".chars(), &[], target::default()).unwrap();
+	let attrs = "#[repr(C)]\n#[derive(Copy, Clone)]\n#[allow(non_camel_case_types, non_snake_case)]\n";
+	assert_eq!(generate(&ns).0, format!(
+		"{}pub struct s {{\n\tpub next: *mut s,\n\tpub data: *mut u8,\n}}\n", attrs))
+}
+
+#[test]
+fn test_constants() {
+	use pre::{preprocess, Macros};
+	use super::constants;
+	let mut macros = Macros::new();
+	preprocess("#define A 0x10\n#define B (A + IMAGE_SIZEOF_SHORT_NAME)\n#define F(x) x\n#define P void*", &mut macros).unwrap();
+	assert_eq!(constants(&macros), "pub const A: u64 = 16;\npub const B: u64 = 24;\n")
+}
+
+#[test]
+fn test_doc_comments() {
+	use target;
+	use pre::{preprocess, Macros};
+	use prs::compile;
+	use super::generate;
+	let (code, comments) = preprocess("struct s { DWORD val; // in bytes\n};", &mut Macros::new()).unwrap();
+	let ns = compile(&mut code.chars(), &comments, target::default()).unwrap();
+	let attrs = "#[repr(C)]\n#[derive(Copy, Clone)]\n#[allow(non_camel_case_types, non_snake_case)]\n";
+	assert_eq!(generate(&ns).0, format!("{}pub struct s {{\n\t/// in bytes\n\tpub val: u32,\n}}\n", attrs))
+}
+
+#[test]
+fn test_bitfields() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let ns = compile(&mut "struct s { DWORD a : 3; DWORD b : 5; BYTE c; };".chars(), &[], target::default()).unwrap();
+	let attrs = "#[repr(C)]\n#[derive(Copy, Clone)]\n#[allow(non_camel_case_types, non_snake_case)]\n";
+	assert_eq!(generate(&ns).0, format!("{}pub struct s {{\n\tpub _bitfield0: u32,\n\tpub c: u8,\n}}\n\n\
+impl s {{\n\
+	\tpub fn a(&self)->u32 {{\n\t\t(self._bitfield0 >> 0) & 0x7\n\t}}\n\
+	\tpub fn set_a(&mut self, value: u32) {{\n\t\tself._bitfield0 = (self._bitfield0 & !(0x7 << 0)) | ((value & 0x7) << 0)\n\t}}\n\n\
+	\tpub fn b(&self)->u32 {{\n\t\t(self._bitfield0 >> 3) & 0x1f\n\t}}\n\
+	\tpub fn set_b(&mut self, value: u32) {{\n\t\tself._bitfield0 = (self._bitfield0 & !(0x1f << 3)) | ((value & 0x1f) << 3)\n\t}}\n\
+}}\n", attrs))
+}
+
+#[test]
+fn test_repr() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let code = "__pragma(pack(push, 1)) struct a { BYTE b; DWORD d; }; __pragma(pack(pop))\n\
+		struct __declspec(align(16)) b { DWORD d; };";
+	let ns = compile(&mut code.chars(), &[], target::default()).unwrap();
+	let attrs = "#[derive(Copy, Clone)]\n#[allow(non_camel_case_types, non_snake_case)]\n";
+	assert_eq!(generate(&ns).0, format!(
+		"#[repr(C, packed(1))]\n{}pub struct a {{\n\tpub b: u8,\n\tpub d: u32,\n}}\n\n#[repr(C, align(16))]\n{}pub struct b {{\n\tpub d: u32,\n}}\n",
+		attrs, attrs))
+}
+
+#[test]
+fn test_enum() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let ns = compile(&mut "enum e { A, B = 0xFFFFFFFF };".chars(), &[], target::default()).unwrap();
+	assert_eq!(generate(&ns).0,
+		"#[repr(C)]\n#[derive(Copy, Clone, PartialEq, Eq, Debug)]\n#[allow(non_camel_case_types)]\npub struct e(pub i32);\n\n\
+		impl e {\n\tpub const A: e = e(0);\n\tpub const B: e = e(-1);\n}\n")
+}
+
+#[test]
+fn test_function_pointers() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let code = "typedef VOID (NTAPI *PIMAGE_TLS_CALLBACK)(PVOID, DWORD, PVOID);\n\
+		struct s { PIMAGE_TLS_CALLBACK tls; DWORD (__cdecl *cb)(void); };";
+	let mut ns = compile(&mut code.chars(), &[], target::default()).unwrap();
+	ns.resolve(target::default());
+	assert_eq!(generate(&ns).0,
+		"#[repr(C)]\n#[derive(Copy, Clone)]\n#[allow(non_camel_case_types, non_snake_case)]\npub struct s {\n\
+		\tpub tls: Option<unsafe extern \"system\" fn(*mut ::std::os::raw::c_void, u32, *mut ::std::os::raw::c_void)>,\n\
+		\tpub cb: Option<unsafe extern \"C\" fn() -> u32>,\n}\n\n\
+		#[allow(non_camel_case_types)]\npub type PIMAGE_TLS_CALLBACK = \
+		Option<unsafe extern \"system\" fn(*mut ::std::os::raw::c_void, u32, *mut ::std::os::raw::c_void)>;\n")
+}
+
+#[test]
+fn test_externs() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let code = "BOOL WINAPI CloseHandle(_In_ HANDLE hObject);\nVOID WINAPI Sleep(_In_ DWORD dwMilliseconds);";
+	let mut ns = compile(&mut code.chars(), &[], target::default()).unwrap();
+	ns.link("kernel32");
+	assert_eq!(generate(&ns).0,
+		"#[link(name = \"kernel32\")]\nextern \"system\" {\n\
+		\tpub fn CloseHandle(hObject: *mut ::std::os::raw::c_void) -> i32;\n\
+		\tpub fn Sleep(dwMilliseconds: u32);\n}\n")
+}
+
+#[test]
+fn test_qualifiers() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let code = "struct s { unsigned __int64 v; const char *name; volatile LONG *p; };";
+	let mut ns = compile(&mut code.chars(), &[], target::default()).unwrap();
+	ns.resolve(target::default());
+	assert_eq!(generate(&ns).0,
+		"#[repr(C)]\n#[derive(Copy, Clone)]\n#[allow(non_camel_case_types, non_snake_case)]\npub struct s {\n\
+		\tpub v: u64,\n\tpub name: *const i8,\n\tpub p: *mut i32,\n}\n")
+}
+
+#[test]
+fn test_self_reference() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let code = "struct _SINGLE_LIST_ENTRY; typedef struct _SINGLE_LIST_ENTRY *PSINGLE_LIST_ENTRY;\n\
+		struct _SINGLE_LIST_ENTRY { PSINGLE_LIST_ENTRY Next; };";
+	let mut ns = compile(&mut code.chars(), &[], target::default()).unwrap();
+	ns.resolve(target::default());
+	assert_eq!(generate(&ns).0,
+		"#[repr(C)]\n#[derive(Copy, Clone)]\n#[allow(non_camel_case_types, non_snake_case)]\npub struct _SINGLE_LIST_ENTRY {\n\
+		\tpub Next: *mut _SINGLE_LIST_ENTRY,\n}\n\n\
+		#[allow(non_camel_case_types)]\npub type PSINGLE_LIST_ENTRY = *mut _SINGLE_LIST_ENTRY;\n")
+}
+
+#[test]
+fn test_flexible() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let ns = compile(&mut "struct n { DWORD Length; WCHAR FileName[1]; };".chars(), &[], target::default()).unwrap();
+	assert_eq!(generate(&ns).0,
+		"#[repr(C)]\n#[derive(Copy, Clone)]\n#[allow(non_camel_case_types, non_snake_case)]\npub struct n {\n\
+		\tpub Length: u32,\n\tpub FileName: [u16; 1],\n}\n\nimpl n {\n\
+		\t/// Bytes needed to hold `count` elements of `FileName`.\n\
+		\tpub fn alloc_size(count: usize)->usize {\n\
+		\t\t::std::cmp::max(::std::mem::size_of::<Self>(), 4 + count * ::std::mem::size_of::<u16>())\n\t}\n\
+		\t/// `this` must point to an allocation of at least `alloc_size(count)` bytes.\n\
+		\tpub unsafe fn FileName<'a>(this: *const Self, count: usize)->&'a [u16] {\n\
+		\t\t::std::slice::from_raw_parts((this as *const u8).offset(4) as *const u16, count)\n\t}\n\
+		\t/// `this` must point to an allocation of at least `alloc_size(count)` bytes.\n\
+		\tpub unsafe fn FileName_mut<'a>(this: *mut Self, count: usize)->&'a mut [u16] {\n\
+		\t\t::std::slice::from_raw_parts_mut((this as *mut u8).offset(4) as *mut u16, count)\n\t}\n}\n")
+}
+
+#[test]
+fn test_named_nested() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let ns = compile(&mut "struct s { BYTE b; union { DWORD PhysicalAddress; WORD VirtualSize; } Misc; };".chars(), &[], target::default()).unwrap();
+	let attrs = "#[repr(C)]\n#[derive(Copy, Clone)]\n#[allow(non_camel_case_types, non_snake_case)]\n";
+	assert_eq!(generate(&ns).0,
+		format!("{0}pub union s_Misc {{\n\tpub PhysicalAddress: u32,\n\tpub VirtualSize: u16,\n}}\n\n\
+			{0}pub struct s {{\n\tpub b: u8,\n\t_padding0: [u8; 3],\n\tpub Misc: s_Misc,\n}}\n", attrs))
+}
+
+#[test]
+fn test_primitive_typedefs() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let code = "typedef LONG L; typedef FLOAT F; typedef HANDLE H; typedef GUID G;\nstruct s { L l; F f; H h; G g; };";
+	let mut ns = compile(&mut code.chars(), &[], target::default()).unwrap();
+	ns.resolve(target::default());
+	assert!(generate(&ns).0.contains("pub struct s {\n\tpub l: i32,\n\tpub f: f32,\n\tpub h: *mut ::std::os::raw::c_void,\n\tpub g: [u32; 4],\n}\n"));
+	let ns = compile(&mut "typedef DWORD u32;".chars(), &[], target::default()).unwrap();
+	assert_eq!(generate(&ns), (String::new(), Vec::new()))
+}
+
+#[test]
+fn test_nested_declarators() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let x64 = target::lookup("x64").unwrap();
+	let code = "struct s { struct X { DWORD a; } x, *px; union { DWORD d; BYTE c[6]; } u[2], *pu; };";
+	let mut ns = compile(&mut code.chars(), &[], x64).unwrap();
+	ns.resolve(x64);
+	let (out, errors) = generate(&ns);
+	assert_eq!(errors, Vec::<String>::new());
+	assert!(out.contains("pub struct X {\n\tpub a: u32,\n}\n"));
+	assert!(out.contains("pub union s_u {\n\tpub d: u32,\n\tpub c: [u8; 6],\n}\n"));
+	assert!(out.contains("pub struct s {\n\tpub x: X,\n\t_padding0: [u8; 4],\n\tpub px: *mut X,\n\tpub u: [s_u; 2],\n\tpub pu: *mut s_u,\n}\n"))
+}
+
+#[test]
+fn test_untagged_pointer_typedefs() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let code = "typedef struct { DWORD a; } *PT, T;\ntypedef union { DWORD d; } *PU;";
+	let mut ns = compile(&mut code.chars(), &[], target::default()).unwrap();
+	ns.resolve(target::default());
+	let (out, errors) = generate(&ns);
+	assert_eq!(errors, Vec::<String>::new());
+	assert!(out.contains("pub struct T {\n\tpub a: u32,\n}\n"));
+	assert!(out.contains("pub union _PU {\n\tpub d: u32,\n}\n"));
+	assert!(out.contains("pub type PT = *mut T;\n"));
+	assert!(out.contains("pub type PU = *mut _PU;\n"))
+}
+
+#[test]
+fn test_unknown_pointee() {
+	use target;
+	use prs::compile;
+	use super::generate;
+	let ns = compile(&mut "struct s { struct X *p; };".chars(), &[], target::default()).unwrap();
+	let (out, errors) = generate(&ns);
+	assert!(out.contains("pub p: *mut ::std::os::raw::c_void,\n"));
+	assert_eq!(errors, vec![format!("type X is unknown, pointing to c_void instead")])
+}
@@ -11,32 +11,128 @@ mod web;
 mod prs;
 mod pre;
 mod tok;
+mod gen;
+mod sdk;
+mod target;
+mod diff;
+
+#[cfg(not(test))]
+fn build(sources: &Vec<(String, String, Option<String>)>, defines: &Vec<String>, target: &target::Target, note: &str)
+	->(prs::GlobalNameSpace, pre::Macros) {
+	let mut global = prs::GlobalNameSpace::new();
+	let mut macros = pre::Macros::new();
+	for def in target.predefined().into_iter().chain(defines.iter().map(|x| &**x)) {
+		if let Err(e) = macros.predefine(def) {
+			println!("{}error: {}", note, e)
+		}
+	}
+	for &(ref url, ref code, ref library) in sources.iter() {
+		let rslt = pre::preprocess(code, &mut macros)
+			.and_then(|(code, comments)| prs::compile(&mut code.chars(), &comments, target))
+			.and_then(|mut x| {
+				if let &Some(ref library) = library {
+					x.link(library)
+				}
+				global.merge(x)
+			});
+		match rslt {
+			Ok(()) =>(),
+			Err(e) =>println!("{}error: {} ({})", note, e, url)
+		}
+	}
+	for e in global.resolve(target).iter() {
+		println!("{}unresolved: {}", note, e)
+	}
+	(global, macros)
+}
 
 #[cfg(not(test))]
 fn main() {
 	use std::fs::File;
+	use std::env;
+	let args = env::args().collect::<Vec<_>>();
+	let rust = args.iter().any(|arg| arg == "--rust");
+	let note = if rust { "// " } else { "" };
 	let config = cfg::load_config(&mut File::open("config.toml").unwrap()).unwrap();
-	for page in web::fetch_contents(&config).unwrap().iter() {
-		println!("[{}]", page.url);
+	let mut targets = Vec::new();
+	for (idx, arg) in args.iter().enumerate() {
+		if arg == "--target" {
+			match args.get(idx + 1) {
+				Some(x) if !x.starts_with("--") =>targets.push(x.clone()),
+				_ =>{
+					println!("{}error: --target needs a target name", note);
+					return
+				}
+			}
+		}
+	}
+	let compare = args.iter().any(|arg| arg == "--diff");
+	if targets.is_empty() {
+		targets = config.targets.clone()
+	}
+	if compare && targets.len() < 2 {
+		// a single target is compared against x86, or against x64 when it is x86 itself
+		if targets.is_empty() {
+			targets.push(format!("x86"))
+		}
+		let other = if target::lookup(&targets[0]) == target::lookup("x86") { "x64" } else { "x86" };
+		targets.push(other.to_string())
+	}
+	let targets = match targets.iter().map(|x| target::lookup(x).ok_or(x.clone())).collect::<Result<Vec<_>, _>>() {
+		Ok(ref x) if x.is_empty() =>vec![target::default()],
+		Ok(x) =>x,
+		Err(x) =>{
+			let valid = target::names().iter().fold(String::new(), |acc, x| if acc.is_empty() {
+				x.to_string()
+			} else {
+				acc + ", " + x
+			});
+			println!("{}error: unknown target {}, valid targets are {}", note, x, valid);
+			return
+		}
+	};
+	let mut sources = Vec::new();
+	for page in web::fetch_contents(&config.urls).unwrap().iter() {
+		println!("{}[{}]", note, page.url);
 		let code_blocks = web::find_code_blocks(&page.content);
+		let library = web::find_library(&page.content);
 		let cnt = code_blocks.len();
 		match cnt {
-			0 =>println!("no code blocks here, page size {}", page.content.len()),
+			0 =>println!("{}no code blocks here, page size {}", note, page.content.len()),
 			_ =>{
-				println!("{} code block(s):", cnt);
+				println!("{}{} code block(s):", note, cnt);
 				for block in code_blocks.iter() {
 					match web::decode(block) {
-						Ok(code) =>{
-							let code = &pre::remove_single_line_comments(&code);
-							match prs::compile(&mut code.chars()) {
-								Ok(x) =>println!("{:?}", x),
-								Err(e) =>println!("error: {}", e)
-							}
-						},
-						Err(e) =>println!("error: {}", e)
+						Ok(code) =>sources.push((page.url.clone(), code, library.clone())),
+						Err(e) =>println!("{}error: {}", note, e)
 					}
 				}
 			}
 		}
 	}
+	if compare {
+		println!("");
+		let (lhs, _) = build(&sources, &config.defines, targets[0], note);
+		let (rhs, _) = build(&sources, &config.defines, targets[1], note);
+		print!("{}", diff::report(&lhs, targets[0], &rhs, targets[1]));
+		return
+	}
+	for target in targets.iter() {
+		println!("");
+		println!("{}[target {}]", note, target.name);
+		let (global, macros) = build(&sources, &config.defines, target, note);
+		if rust {
+			let (code, errors) = gen::generate(&global);
+			for e in errors.iter() {
+				println!("{}error: {}", note, e)
+			}
+			let consts = gen::constants(&macros);
+			if !consts.is_empty() {
+				println!("{}", consts)
+			}
+			println!("{}", code)
+		} else {
+			println!("{:?}", global)
+		}
+	}
 }
@@ -1,23 +1,510 @@
+use std::slice::Iter as SliceIter;
+use sdk;
+use tok::parse_number;
+
 #[cfg(test)]
 mod tests;
 
-pub fn remove_single_line_comments(code: &str)->String {
-	let mut iter = code.chars();
+#[derive(Debug, PartialEq, Clone)]
+pub struct Comment {
+	pub line: usize,
+	pub text: String,
+	pub trailing: bool
+}
+
+fn comment_text(text: &str)->String {
+	text.lines()
+		.map(|x| x.trim().trim_left_matches('*').trim())
+		.filter(|x| !x.is_empty())
+		.fold(String::new(), |acc, x| if acc.is_empty() { x.to_string() } else { acc + " " + x })
+}
+
+pub fn strip_comments(code: &str)->(String, Vec<Comment>) {
+	let chars = code.chars().collect::<Vec<_>>();
 	let mut ret = String::new();
-	while let Some(c) = iter.next() {
+	let mut comments = Vec::new();
+	let mut line = 1;
+	let mut idx = 0;
+	while idx < chars.len() {
+		let c = chars[idx];
+		let next = chars.get(idx + 1).cloned();
 		match c {
-			'/' =>match iter.next() {
-				Some('/') =>while let Some(c) = iter.next() {
-					if c == '\n' {
-						ret.push(c);
+			'"' | '\'' =>{
+				ret.push(c);
+				idx += 1;
+				while idx < chars.len() {
+					let x = chars[idx];
+					ret.push(x);
+					idx += 1;
+					if x == '\n' {
+						line += 1
+					}
+					if x == '\\' && idx < chars.len() {
+						ret.push(chars[idx]);
+						idx += 1
+					} else if x == c || x == '\n' {
 						break
 					}
-				},
-				Some(x) =>ret.push(x),
-				None =>()
+				}
+				continue
 			},
-			_ =>ret.push(c)
+			'/' if next == Some('/') || next == Some('*') =>{
+				let trailing = !ret.rsplit('\n').next().unwrap_or("").trim().is_empty();
+				let start = idx + 2;
+				let (end, skip) = if next == Some('/') {
+					match (start .. chars.len()).find(|&i| chars[i] == '\n') {
+						Some(i) =>(i, i),
+						None =>(chars.len(), chars.len())
+					}
+				} else {
+					match (start .. chars.len()).find(|&i| chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+						Some(i) =>(i, i + 2),
+						None =>(chars.len(), chars.len())
+					}
+				};
+				let text = chars[start .. end].iter().cloned().collect::<String>();
+				comments.push(Comment { line: line, text: comment_text(&text), trailing: trailing });
+				if next == Some('*') {
+					ret.push(' ')
+				}
+				for _ in text.matches('\n') {
+					ret.push('\n');
+					line += 1
+				}
+				idx = skip;
+				continue
+			},
+			'\n' =>line += 1,
+			_ =>()
 		}
+		ret.push(c);
+		idx += 1
 	}
+	(ret, comments)
+}
+
+pub fn remove_comments(code: &str)->String {
+	strip_comments(code).0
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Macro {
+	pub name: String,
+	pub params: Option<Vec<String>>,
+	pub body: String,
+	pub predefined: bool
+}
+
+#[derive(Debug, Clone)]
+pub struct Macros(Vec<Macro>);
+
+fn is_ident_char(c: char)->bool {
+	c == '_' || c.is_alphanumeric()
+}
+
+fn split_ident(s: &str)->(&str, &str) {
+	let end = s.find(|c: char| !is_ident_char(c)).unwrap_or(s.len());
+	(&s[.. end], &s[end ..])
+}
+
+fn arguments(chars: &Vec<char>, start: usize)->Result<(Vec<String>, usize), String> {
+	let mut ret = Vec::new();
+	let mut arg = String::new();
+	let mut depth = 0;
+	let mut idx = start;
+	while idx < chars.len() {
+		let c = chars[idx];
+		idx += 1;
+		match c {
+			'(' =>depth += 1,
+			')' if depth == 0 =>{
+				ret.push(arg.trim().to_string());
+				return Ok((ret, idx))
+			},
+			')' =>depth -= 1,
+			',' if depth == 0 =>{
+				ret.push(arg.trim().to_string());
+				arg.clear();
+				continue
+			},
+			_ =>()
+		}
+		arg.push(c)
+	}
+	Err(format!("unterminated macro arguments"))
+}
+
+fn substitute(body: &str, params: &Vec<String>, args: &Vec<String>)->String {
+	let mut ret = String::new();
+	let mut rest = body;
+	while let Some(start) = rest.find(is_ident_char) {
+		ret.push_str(&rest[.. start]);
+		let (word, tail) = split_ident(&rest[start ..]);
+		match params.iter().position(|x| x == word) {
+			Some(idx) =>ret.push_str(&args[idx]),
+			None =>ret.push_str(word)
+		}
+		rest = tail
+	}
+	ret.push_str(rest);
 	ret
 }
+
+impl Macros {
+	pub fn new()->Macros {
+		Macros(Vec::new())
+	}
+	
+	pub fn iter(&self)->SliceIter<Macro> {
+		self.0.iter()
+	}
+	
+	pub fn get(&self, name: &str)->Option<&Macro> {
+		self.0.iter().find(|x| x.name == name)
+	}
+	
+	pub fn undefine(&mut self, name: &str) {
+		self.0.retain(|x| x.name != name)
+	}
+	
+	pub fn define(&mut self, line: &str)->Result<(), String> {
+		let (name, rest) = split_ident(line.trim_left());
+		if name.is_empty() || name.chars().next().unwrap().is_digit(10) {
+			return Err(format!("illegal macro name in #define {}", line.trim()))
+		}
+		let (params, body) = if rest.starts_with("(") {
+			let end = match rest.find(')') {
+				Some(x) =>x,
+				None =>return Err(format!("unterminated parameter list of macro {}", name))
+			};
+			let params = rest[1 .. end].split(',').map(|x| x.trim().to_string())
+				.filter(|x| !x.is_empty()).collect::<Vec<_>>();
+			(Some(params), &rest[end + 1 ..])
+		} else {
+			(None, rest)
+		};
+		let value = Macro { name: name.to_string(), params: params, body: body.trim().to_string(), predefined: false };
+		match self.0.iter().position(|x| x.name == name) {
+			Some(idx) =>self.0[idx] = value,
+			None =>self.0.push(value)
+		}
+		Ok(())
+	}
+	
+	pub fn predefine(&mut self, def: &str)->Result<(), String> {
+		let (name, value) = match def.find('=') {
+			Some(idx) =>(&def[.. idx], &def[idx + 1 ..]),
+			None =>(def, "1")
+		};
+		try!(self.define(&format!("{} {}", name, value)));
+		let name = split_ident(name.trim()).0;
+		for x in self.0.iter_mut().filter(|x| x.name == name) {
+			x.predefined = true
+		}
+		Ok(())
+	}
+	
+	pub fn expand(&self, code: &str)->Result<String, String> {
+		self.expand_with(code, &mut Vec::new())
+	}
+	
+	fn expand_with(&self, code: &str, active: &mut Vec<String>)->Result<String, String> {
+		let chars = code.chars().collect::<Vec<_>>();
+		let mut ret = String::new();
+		let mut idx = 0;
+		while idx < chars.len() {
+			if !is_ident_char(chars[idx]) {
+				ret.push(chars[idx]);
+				idx += 1;
+				continue
+			}
+			let start = idx;
+			while idx < chars.len() && is_ident_char(chars[idx]) {
+				idx += 1
+			}
+			let word = chars[start .. idx].iter().cloned().collect::<String>();
+			let value = match self.get(&word) {
+				Some(x) if !chars[start].is_digit(10) && !active.contains(&word) =>x,
+				_ =>{
+					ret.push_str(&word);
+					continue
+				}
+			};
+			let body = match value.params {
+				None =>value.body.clone(),
+				Some(ref params) =>{
+					let mut next = idx;
+					while next < chars.len() && chars[next].is_whitespace() {
+						next += 1
+					}
+					if next >= chars.len() || chars[next] != '(' {
+						ret.push_str(&word);
+						continue
+					}
+					let (mut args, end) = try!(arguments(&chars, next + 1));
+					// `F()` passes one empty argument, unless F takes none
+					if params.is_empty() && args.len() == 1 && args[0].is_empty() {
+						args.clear()
+					}
+					if args.len() != params.len() {
+						return Err(format!("macro {} expects {} argument(s), got {}", word, params.len(), args.len()))
+					}
+					idx = end;
+					substitute(&value.body, params, &args)
+				}
+			};
+			active.push(word);
+			let expanded = try!(self.expand_with(&body, active));
+			active.pop();
+			ret.push_str(&expanded)
+		}
+		Ok(ret)
+	}
+}
+
+fn expression_tokens(expr: &str)->Result<Vec<String>, String> {
+	let mut ret = Vec::new();
+	let mut rest = expr.trim_left();
+	while !rest.is_empty() {
+		let len = if rest.starts_with(is_ident_char) {
+			split_ident(rest).0.len()
+		} else if ["||", "&&", "==", "!=", "<=", ">=", "<<", ">>"].iter().any(|x| rest.starts_with(x)) {
+			2
+		} else if rest.starts_with(|c| "|^&<>+-*/%!~()".contains(c)) {
+			1
+		} else {
+			return Err(format!("unexpected character in expression {}", expr.trim()))
+		};
+		ret.push(rest[.. len].to_string());
+		rest = rest[len ..].trim_left()
+	}
+	Ok(ret)
+}
+
+static LEVELS: [&'static [&'static str]; 10] = [
+	&["||"], &["&&"], &["|"], &["^"], &["&"], &["==", "!="],
+	&["<", ">", "<=", ">="], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]
+];
+
+struct Expression(Vec<String>, usize);
+
+impl Expression {
+	fn peek(&self)->Option<&str> {
+		self.0.get(self.1).map(|x| &**x)
+	}
+	
+	fn read(&mut self)->Result<String, String> {
+		match self.0.get(self.1) {
+			Some(x) =>{
+				self.1 += 1;
+				Ok(x.clone())
+			},
+			None =>Err(format!("unexpected end of expression"))
+		}
+	}
+	
+	fn primary(&mut self)->Result<i64, String> {
+		let tok = try!(self.read());
+		match &*tok {
+			"(" =>{
+				let ret = try!(self.binary(0));
+				match &*try!(self.read()) {
+					")" =>Ok(ret),
+					x =>Err(format!("unexpected {} in expression", x))
+				}
+			},
+			"!" =>Ok((try!(self.primary()) == 0) as i64),
+			"~" =>Ok(!try!(self.primary())),
+			"-" =>try!(self.primary()).checked_neg().ok_or(format!("overflow in expression")),
+			"+" =>self.primary(),
+			x if x.starts_with(|c: char| c.is_digit(10)) =>parse_number(x).map(|x| x as i64),
+			x if x.starts_with(is_ident_char) =>Ok(sdk::constant(x).unwrap_or(0) as i64),
+			x =>Err(format!("unexpected {} in expression", x))
+		}
+	}
+	
+	fn binary(&mut self, level: usize)->Result<i64, String> {
+		if level == LEVELS.len() {
+			return self.primary()
+		}
+		let mut lhs = try!(self.binary(level + 1));
+		loop {
+			let op = match self.peek() {
+				Some(op) if LEVELS[level].contains(&op) =>op.to_string(),
+				_ =>return Ok(lhs)
+			};
+			self.1 += 1;
+			let rhs = try!(self.binary(level + 1));
+			lhs = match &*op {
+				"||" =>(lhs != 0 || rhs != 0) as i64,
+				"&&" =>(lhs != 0 && rhs != 0) as i64,
+				"|" =>lhs | rhs,
+				"^" =>lhs ^ rhs,
+				"&" =>lhs & rhs,
+				"==" =>(lhs == rhs) as i64,
+				"!=" =>(lhs != rhs) as i64,
+				"<" =>(lhs < rhs) as i64,
+				">" =>(lhs > rhs) as i64,
+				"<=" =>(lhs <= rhs) as i64,
+				">=" =>(lhs >= rhs) as i64,
+				"<<" | ">>" if rhs < 0 || rhs >= 64 =>return Err(format!("invalid shift count {} in expression", rhs)),
+				"<<" =>lhs << rhs,
+				">>" =>lhs >> rhs,
+				"+" =>try!(lhs.checked_add(rhs).ok_or(format!("overflow in expression"))),
+				"-" =>try!(lhs.checked_sub(rhs).ok_or(format!("overflow in expression"))),
+				"*" =>try!(lhs.checked_mul(rhs).ok_or(format!("overflow in expression"))),
+				_ if rhs == 0 =>return Err(format!("division by zero in expression")),
+				"/" =>try!(lhs.checked_div(rhs).ok_or(format!("overflow in expression"))),
+				_ =>try!(lhs.checked_rem(rhs).ok_or(format!("overflow in expression")))
+			}
+		}
+	}
+}
+
+fn replace_defined(expr: &str, macros: &Macros)->Result<String, String> {
+	let mut ret = String::new();
+	let mut rest = expr;
+	while let Some(start) = rest.find(is_ident_char) {
+		ret.push_str(&rest[.. start]);
+		let (word, tail) = split_ident(&rest[start ..]);
+		rest = tail;
+		if word != "defined" {
+			ret.push_str(word);
+			continue
+		}
+		let paren = rest.trim_left().starts_with("(");
+		let (name, tail) = split_ident(rest.trim_left().trim_left_matches('(').trim_left());
+		if name.is_empty() {
+			return Err(format!("missing macro name after defined"))
+		}
+		rest = tail.trim_left();
+		if paren {
+			if !rest.starts_with(")") {
+				return Err(format!("missing ) after defined({}", name))
+			}
+			rest = &rest[1 ..]
+		}
+		ret.push_str(if macros.get(name).is_some() { " 1 " } else { " 0 " })
+	}
+	ret.push_str(rest);
+	Ok(ret)
+}
+
+fn condition(expr: &str, macros: &Macros)->Result<bool, String> {
+	let expr = try!(macros.expand(&try!(replace_defined(expr, macros))));
+	let mut stream = Expression(try!(expression_tokens(&expr)), 0);
+	let ret = try!(stream.binary(0));
+	match stream.peek() {
+		Some(x) =>Err(format!("unexpected {} in expression", x)),
+		None =>Ok(ret != 0)
+	}
+}
+
+struct Conditional {
+	parent: bool,
+	taken: bool,
+	active: bool
+}
+
+// lines of directives and inactive branches go to `skipped`, comments on them describe nothing in the output
+fn process(code: &str, macros: &mut Macros, skipped: &mut Vec<usize>)->Result<String, String> {
+	let mut ret = String::new();
+	let mut pending = String::new();
+	let mut stack: Vec<Conditional> = Vec::new();
+	let mut lines = code.split('\n');
+	let mut number = 0;
+	while let Some(line) = lines.next() {
+		number += 1;
+		let active = stack.last().map(|x| x.active).unwrap_or(true);
+		if !line.trim_left().starts_with("#") {
+			if active {
+				pending.push_str(line)
+			} else {
+				skipped.push(number)
+			}
+			pending.push('\n');
+			continue
+		}
+		skipped.push(number);
+		ret.push_str(&try!(macros.expand(&pending)));
+		pending.clear();
+		let mut directive = line.trim().to_string();
+		let mut cnt = 1;
+		while directive.ends_with("\\") {
+			directive.pop();
+			directive.push(' ');
+			match lines.next() {
+				Some(next) =>directive.push_str(next.trim()),
+				None =>break
+			}
+			cnt += 1;
+			number += 1;
+			skipped.push(number)
+		}
+		let (keyword, rest) = split_ident(directive[1 ..].trim_left());
+		match keyword {
+			"if" | "ifdef" | "ifndef" =>{
+				let value = active && match keyword {
+					"ifdef" =>macros.get(split_ident(rest.trim_left()).0).is_some(),
+					"ifndef" =>macros.get(split_ident(rest.trim_left()).0).is_none(),
+					_ =>try!(condition(rest, macros))
+				};
+				stack.push(Conditional { parent: active, taken: value, active: value })
+			},
+			"elif" =>match stack.last_mut() {
+				Some(top) =>{
+					top.active = top.parent && !top.taken && try!(condition(rest, macros));
+					top.taken = top.taken || top.active
+				},
+				None =>return Err(format!("#elif without #if"))
+			},
+			"else" =>match stack.last_mut() {
+				Some(top) =>{
+					top.active = top.parent && !top.taken;
+					top.taken = true
+				},
+				None =>return Err(format!("#else without #if"))
+			},
+			"endif" =>if stack.pop().is_none() {
+				return Err(format!("#endif without #if"))
+			},
+			"define" if active =>try!(macros.define(rest)),
+			"undef" if active =>macros.undefine(rest.trim()),
+			"pragma" if active =>{
+				let (name, args) = split_ident(rest.trim_left());
+				if name == "pack" {
+					ret.push_str(&format!("__pragma(pack{})", args.trim()))
+				}
+			},
+			"include" if active =>{
+				let header = rest.trim().trim_matches(|c| c == '<' || c == '>' || c == '"').to_lowercase();
+				if header.starts_with("pshpack") && header.ends_with(".h") {
+					ret.push_str(&format!("__pragma(pack(push, {}))", &header[7 .. header.len() - 2]))
+				} else if header == "poppack.h" {
+					ret.push_str("__pragma(pack(pop))")
+				}
+			},
+			_ =>()
+		}
+		for _ in 0 .. cnt {
+			ret.push('\n')
+		}
+	}
+	if !stack.is_empty() {
+		return Err(format!("unterminated #if"))
+	}
+	if pending.ends_with("\n") {
+		pending.pop();
+	} else if ret.ends_with("\n") {
+		ret.pop();
+	}
+	ret.push_str(&try!(macros.expand(&pending)));
+	Ok(ret)
+}
+
+pub fn preprocess(code: &str, macros: &mut Macros)->Result<(String, Vec<Comment>), String> {
+	let (code, comments) = strip_comments(code);
+	let mut skipped = Vec::new();
+	let ret = try!(process(&code, macros, &mut skipped));
+	Ok((ret, comments.into_iter().filter(|x| !x.text.is_empty() && !skipped.contains(&x.line)).collect()))
+}
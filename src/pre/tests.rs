@@ -1,5 +1,59 @@
 #[test]
 fn test_comment() {
-	use super::remove_single_line_comments as remove_comments;
+	use super::remove_comments;
 	assert_eq!(remove_comments("//"), "")
 }
+
+#[test]
+fn test_block_comment() {
+	use super::{strip_comments, Comment};
+	let code = "/* header\n * line */\nstruct s {\n\tDWORD a; // first\n\tBYTE b[4 / 2]; /* \"*/\n\tchar *c = \"// /*\";\n};";
+	let (code, comments) = strip_comments(code);
+	assert_eq!(code, " \n\nstruct s {\n\tDWORD a; \n\tBYTE b[4 / 2];  \n\tchar *c = \"// /*\";\n};");
+	assert_eq!(comments, vec![
+		Comment { line: 1, text: format!("header line"), trailing: false },
+		Comment { line: 4, text: format!("first"), trailing: true },
+		Comment { line: 5, text: format!("\""), trailing: true }])
+}
+
+#[test]
+fn test_define() {
+	use super::{preprocess, Macros};
+	let mut macros = Macros::new();
+	let code = "#define SIZE 8\n#define TWICE(x) ((x) * 2)\n#define LONG_NAME \\\n\tSIZE\nBYTE a[TWICE(SIZE)];\nBYTE b[LONG_NAME];";
+	assert_eq!(preprocess(code, &mut macros).unwrap().0, "\n\n\n\nBYTE a[((8) * 2)];\nBYTE b[8];");
+	assert_eq!(macros.iter().map(|x| x.name.clone()).collect::<Vec<_>>(),
+		vec![format!("SIZE"), format!("TWICE"), format!("LONG_NAME")]);
+	let code = "#define NONE() 1\n#define ONE(x) (x + 1)\nBYTE c[NONE()];\nBYTE d[ONE() 2];";
+	assert_eq!(preprocess(code, &mut macros).unwrap().0, "\n\nBYTE c[1];\nBYTE d[( + 1) 2];")
+}
+
+#[test]
+fn test_undef() {
+	use super::{preprocess, Macros};
+	let mut macros = Macros::new();
+	let code = "#define A B\n#define B A\nA;\n#undef A\nA;";
+	assert_eq!(preprocess(code, &mut macros).unwrap().0, "\n\nA;\n\nA;")
+}
+
+#[test]
+fn test_conditional() {
+	use super::{preprocess, Macros};
+	use target;
+	let code = "#ifdef _WIN64\nA\n#elif defined(_M_IX86) && NTDDI_VERSION >= NTDDI_WIN7\nB\n#else\nC\n#endif\n#if !defined _WIN64\n#define D 4\n#endif\n#ifndef D\nE\n#endif";
+	let run = |name| {
+		let mut macros = Macros::new();
+		for def in target::lookup(name).unwrap().predefined().into_iter() {
+			macros.predefine(def).unwrap()
+		}
+		(preprocess(code, &mut macros).unwrap().0, macros.get("D").is_some())
+	};
+	assert_eq!(run("x64"), (format!("\nA\n\n\n\n\n\n\n\n\n\nE\n"), false));
+	assert_eq!(run("x86"), (format!("\n\n\nB\n\n\n\n\n\n\n\n\n"), true));
+	let mut macros = Macros::new();
+	assert!(preprocess("#if 1\nA", &mut macros).is_err());
+	assert!(preprocess("#endif", &mut macros).is_err());
+	assert!(preprocess("#if 1 << 64\n#endif", &mut macros).is_err());
+	assert!(preprocess("#if 0x7FFFFFFFFFFFFFFF * 2\n#endif", &mut macros).is_err());
+	assert!(preprocess("#if (1 << 62) / 0\n#endif", &mut macros).is_err())
+}
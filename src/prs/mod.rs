@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 use std::collections::hash_map::Iter;
-use std::rc::Rc;
+use std::slice::Iter as SliceIter;
 use std::fmt::Result as FmtResult;
 use std::fmt::{
 	Display,
@@ -11,65 +11,479 @@ use tok::{
 	TokenStream,
 	Token
 };
+use sdk;
+use target::Target;
+use pre::Comment;
 
 #[cfg(test)]
 mod tests;
 
-const POINTER_SIZE: usize = 4;
+fn align_to(offset: usize, align: usize)->usize {
+	(offset + align - 1) / align * align
+}
+
+fn cap(align: usize, pack: Option<usize>)->usize {
+	match pack {
+		Some(pack) if pack < align =>pack,
+		_ =>align
+	}
+}
 
 #[derive(PartialEq, Clone)]
-struct Struct(HashMap<String, (usize, usize)>);
+pub struct Nested {
+	pub name: String,
+	pub offset: usize,
+	pub value: Type,
+	pub named: bool
+}
 
-impl Struct {
-	fn iter(&self)->Iter<String, (usize, usize)> {
-		self.0.iter()
+impl Nested {
+	pub fn type_name(&self, parent: &str)->String {
+		format!("{}_{}", parent, self.name)
+	}
+	
+	pub fn size(&self)->usize {
+		aggregate_size(&self.value)
+	}
+	
+	pub fn align(&self)->usize {
+		aggregate_align(&self.value)
+	}
+	
+	// fields of a named member show up in the parent as `name.field`
+	fn prefix(&self)->String {
+		if self.named { format!("{}.", self.name) } else { String::new() }
 	}
 	
-	fn inject_struct(&mut self, s: Struct)->Result<(), String> {
-		let bound = self.size();
-		for (k, &(offset, size)) in s.iter() {
-			if self.layout_mut().insert(k.clone(), (offset + bound, size)).is_some() {
-				return Err(format!("dup of field name {}", k))
+	pub fn contains(&self, field: &str)->bool {
+		let prefix = self.prefix();
+		if !field.starts_with(&prefix) {
+			return false
+		}
+		let field = &field[prefix.len() ..];
+		match self.value {
+			Type::Struct(ref s) =>s.layout().contains_key(field),
+			Type::Union(ref u) =>u.layout().contains_key(field),
+			_ =>false
+		}
+	}
+}
+
+fn dummy_name(name: &str)->Option<String> {
+	for &(prefix, short) in [("DUMMYUNIONNAME", "u"), ("DUMMYSTRUCTNAME", "s")].iter() {
+		if name.starts_with(prefix) {
+			let suffix = &name[prefix.len() ..];
+			if suffix.chars().all(|c| c.is_digit(10)) {
+				return Some(format!("{}{}", short, suffix))
 			}
 		}
-		Ok(())
+	}
+	None
+}
+
+fn anonymous_name(nested: &Vec<Nested>, value: &Type)->String {
+	let prefix = match value {
+		&Type::Union(_) =>"u",
+		_ =>"s"
+	};
+	let mut n = 1;
+	loop {
+		let name = if n == 1 { prefix.to_string() } else { format!("{}{}", prefix, n) };
+		if !nested.iter().any(|x| x.name == name) {
+			return name
+		}
+		n += 1
+	}
+}
+
+fn aggregate_size(value: &Type)->usize {
+	match value {
+		&Type::Struct(ref s) =>s.size(),
+		&Type::Union(ref u) =>u.size(),
+		_ =>panic!("internal error, {:?} is not an aggregate", value)
+	}
+}
+
+fn aggregate_align(value: &Type)->usize {
+	match value {
+		&Type::Struct(ref s) =>s.align(),
+		&Type::Union(ref u) =>u.align(),
+		_ =>panic!("internal error, {:?} is not an aggregate", value)
+	}
+}
+
+fn nested_layout(value: &Type)->Layout {
+	match value {
+		&Type::Struct(ref s) =>s.layout().clone(),
+		&Type::Union(ref u) =>u.layout().clone(),
+		_ =>panic!("internal error, {:?} is not an aggregate", value)
+	}
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Function {
+	pub ret: FieldType,
+	pub convention: Option<String>,
+	pub params: Vec<(String, FieldType)>,
+	pub library: Option<String>
+}
+
+impl Function {
+	fn params(&self)->String {
+		self.params.iter().fold(String::new(), |acc, &(ref name, ref ty)| {
+			let param = if name.is_empty() { format!("{}", ty) } else { format!("{} {}", ty, name) };
+			if acc.is_empty() { param } else { acc + ", " + &param }
+		})
+	}
+	
+	fn write(&self, f: &mut Formatter, inner: &str)->FmtResult {
+		let params = self.params();
+		let decl = match self.convention {
+			Some(ref conv) if inner.is_empty() =>conv.clone(),
+			Some(ref conv) =>format!("{} {}", conv, inner),
+			None =>inner.to_string()
+		};
+		if inner.is_empty() {
+			write!(f, "{} {}({})", self.ret, decl, params)
+		} else {
+			write!(f, "{} ({})({})", self.ret, decl, params)
+		}
+	}
+}
+
+impl Display for Function {
+	fn fmt(&self, f: &mut Formatter)->FmtResult {
+		self.write(f, "")
+	}
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum FieldType {
+	Primitive(String),
+	Named(TypeName),
+	Pointer(Box<FieldType>),
+	Array(Box<FieldType>, usize),
+	Function(Box<Function>),
+	Qualified(Box<FieldType>, Vec<String>),
+	AnonymousStruct,
+	AnonymousUnion
+}
+
+impl FieldType {
+	pub fn is_const(&self)->bool {
+		match self {
+			&FieldType::Qualified(ref ty, ref qualifiers) =>qualifiers.iter().any(|x| x == "const") || ty.is_const(),
+			_ =>false
+		}
+	}
+	
+	fn unqualified(&self)->&FieldType {
+		match self {
+			&FieldType::Qualified(ref ty, _) =>ty.unqualified(),
+			_ =>self
+		}
+	}
+}
+
+impl Display for FieldType {
+	fn fmt(&self, f: &mut Formatter)->FmtResult {
+		match self {
+			&FieldType::Primitive(ref name) =>write!(f, "{}", name),
+			&FieldType::Named(TypeName::Normal(ref name)) |
+			&FieldType::Named(TypeName::Function(ref name)) =>write!(f, "{}", name),
+			&FieldType::Named(TypeName::Struct(ref name)) =>write!(f, "struct {}", name),
+			&FieldType::Named(TypeName::Union(ref name)) =>write!(f, "union {}", name),
+			&FieldType::Named(TypeName::Enum(ref name)) =>write!(f, "enum {}", name),
+			&FieldType::Pointer(_) =>{
+				let mut ty = self;
+				let mut stars = String::new();
+				while let &FieldType::Pointer(ref inner) = ty {
+					stars.push('*');
+					ty = inner
+				}
+				match ty {
+					&FieldType::Function(ref func) =>func.write(f, &stars),
+					_ =>write!(f, "{}{}", ty, stars)
+				}
+			},
+			&FieldType::Function(ref func) =>write!(f, "{}", func),
+			&FieldType::Qualified(ref ty, ref qualifiers) =>{
+				let qualifiers = qualifiers.iter().fold(String::new(), |acc, x| {
+					if acc.is_empty() { x.clone() } else { acc + " " + x }
+				});
+				match **ty {
+					FieldType::Pointer(_) =>write!(f, "{} {}", ty, qualifiers),
+					_ =>write!(f, "{} {}", qualifiers, ty)
+				}
+			},
+			&FieldType::Array(_, _) =>{
+				let mut ty = self;
+				let mut dims = String::new();
+				while let &FieldType::Array(ref inner, cnt) = ty {
+					dims.push_str(&format!("[{}]", cnt));
+					ty = inner
+				}
+				write!(f, "{}{}", ty, dims)
+			},
+			&FieldType::AnonymousStruct =>write!(f, "struct {{...}}"),
+			&FieldType::AnonymousUnion =>write!(f, "union {{...}}")
+		}
+	}
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Field {
+	pub name: String,
+	pub ty: FieldType,
+	pub offset: usize,
+	pub size: usize,
+	pub align: usize,
+	pub bits: Option<(usize, usize)>,
+	pub comments: Vec<String>,
+	pub flexible: bool
+}
+
+#[derive(PartialEq, Clone)]
+pub struct Layout {
+	fields: Vec<Field>,
+	index: HashMap<String, usize>
+}
+
+impl Layout {
+	fn new()->Layout {
+		Layout { fields: Vec::new(), index: HashMap::new() }
 	}
 	
-	fn inject_union(&mut self, u: Union)->Result<(), String> {
-		let bound = self.size();
-		for (k, &(offset, size)) in u.iter() {
-			if self.layout_mut().insert(k.clone(), (offset + bound, size)).is_some() {
-				return Err(format!("dup of field name {}", k))
+	fn insert(&mut self, field: Field)->Result<(), String> {
+		if !field.name.is_empty() {
+			if self.index.contains_key(&field.name) {
+				return Err(format!("dup of field name {}", field.name))
 			}
+			self.index.insert(field.name.clone(), self.fields.len());
+		}
+		self.fields.push(field);
+		Ok(())
+	}
+	
+	fn annotate(&mut self, name: &str, comments: Vec<String>) {
+		if let Some(&idx) = self.index.get(name) {
+			self.fields[idx].comments.extend(comments.into_iter())
+		}
+	}
+	
+	pub fn iter(&self)->SliceIter<Field> {
+		self.fields.iter()
+	}
+	
+	pub fn get(&self, name: &str)->Option<&Field> {
+		self.index.get(name).map(|&idx| &self.fields[idx])
+	}
+	
+	pub fn contains_key(&self, name: &str)->bool {
+		self.index.contains_key(name)
+	}
+	
+	fn is_empty(&self)->bool {
+		self.fields.is_empty()
+	}
+	
+	fn align(&self)->usize {
+		self.iter().fold(1, |acc, field| if acc < field.align { field.align } else { acc })
+	}
+}
+
+fn write_layout(f: &mut Formatter, layout: &Layout, padding: Vec<(usize, usize)>)->FmtResult {
+	let mut padding = padding.into_iter().peekable();
+	for field in layout.iter().filter(|x| x.bits.map_or(true, |(_, width)| width > 0)) {
+		while padding.peek().map_or(false, |&(offset, size)| offset + size <= field.offset) {
+			let (offset, size) = padding.next().unwrap();
+			try!(write!(f, "\t{:32} (padding);\n", format!("{:02X} - {:02X}", offset, offset + size)))
+		}
+		let span = if field.flexible {
+			format!("{:02X} - .. flexible", field.offset)
+		} else if field.size == 0 {
+			format!("{:02X} (offset only, size unknown)", field.offset)
+		} else {
+			format!("{:02X} - {:02X}", field.offset, field.offset + field.size)
+		};
+		try!(match field.bits {
+			Some((bit, width)) =>write!(f, "\t{:32} {} {}: {};",
+				format!("{} bits {:02} - {:02}", span, bit, bit + width), field.ty, field.name, width),
+			None =>write!(f, "\t{:32} {} {};", span, field.ty, field.name)
+		});
+		for comment in field.comments.iter() {
+			try!(write!(f, " // {}", comment))
+		}
+		try!(write!(f, "\n"))
+	}
+	for (offset, size) in padding {
+		try!(write!(f, "\t{:32} (padding);\n", format!("{:02X} - {:02X}", offset, offset + size)))
+	}
+	Ok(())
+}
+
+pub enum Item<'a> {
+	Field(&'a Field),
+	Nested(&'a Nested)
+}
+
+fn items<'a>(layout: &'a Layout, nested: &'a Vec<Nested>)->Vec<Item<'a>> {
+	let mut ret = Vec::new();
+	let mut done = Vec::new();
+	for field in layout.iter() {
+		match nested.iter().position(|x| x.contains(&field.name)) {
+			Some(idx) =>if !done.contains(&idx) {
+				ret.push(Item::Nested(&nested[idx]));
+				done.push(idx)
+			},
+			None =>ret.push(Item::Field(field))
+		}
+	}
+	ret
+}
+
+#[derive(PartialEq, Clone)]
+pub struct Struct {
+	layout: Layout,
+	nested: Vec<Nested>,
+	pack: Option<usize>,
+	declared: Option<usize>
+}
+
+impl Struct {
+	pub fn iter(&self)->SliceIter<Field> {
+		self.layout.iter()
+	}
+	
+	pub fn nested(&self)->&Vec<Nested> {
+		&self.nested
+	}
+	
+	pub fn items(&self)->Vec<Item> {
+		items(&self.layout, &self.nested)
+	}
+	
+	fn inject(&mut self, name: Option<String>, value: Type, named: bool)->Result<(), String> {
+		let bound = align_to(self.end(), cap(aggregate_align(&value), self.pack));
+		let name = name.unwrap_or_else(|| anonymous_name(&self.nested, &value));
+		let nested = Nested { name: name, offset: bound, value: value, named: named };
+		for field in nested_layout(&nested.value).iter().filter(|x| !x.name.is_empty()) {
+			try!(self.layout.insert(Field {
+				name: nested.prefix() + &field.name, offset: field.offset + bound, .. field.clone()
+			}))
 		}
+		self.nested.push(nested);
 		Ok(())
 	}
 	
-	fn insert(&mut self, name: String, size: usize)->Option<(usize, usize)> {
-		let bound = self.size();
-		self.layout_mut().insert(name, (bound, size))
+	fn insert(&mut self, name: String, ty: FieldType, size: usize, align: usize)->Result<(), String> {
+		let align = cap(align, self.pack);
+		let bound = align_to(self.end(), align);
+		self.layout.insert(Field {
+			name: name, ty: ty, offset: bound, size: size, align: align, bits: None, comments: Vec::new(), flexible: false
+		})
+	}
+	
+	fn insert_bits(&mut self, name: String, ty: FieldType, size: usize, align: usize, width: usize)->Result<(), String> {
+		if size == 0 {
+			return Err(format!("bitfield {} needs a primitive type", name))
+		}
+		if width > size * 8 {
+			return Err(format!("bitfield {} is wider than its type", name))
+		}
+		let align = cap(align, self.pack);
+		let unit = match self.items().pop() {
+			Some(Item::Field(last)) =>match last.bits {
+				Some((bit, used)) =>Some((last.offset, last.size, bit + used)),
+				None =>None
+			},
+			_ =>None
+		};
+		let (offset, size, bit) = match unit {
+			Some((offset, unit, _)) if width == 0 =>(offset, unit, unit * 8),
+			None if width == 0 =>return Ok(()),
+			Some((offset, unit, bit)) if unit == size && bit + width <= size * 8 =>(offset, size, bit),
+			_ =>(align_to(self.end(), align), size, 0)
+		};
+		self.layout.insert(Field {
+			name: name, ty: ty, offset: offset, size: size, align: align, bits: Some((bit, width)), comments: Vec::new(), flexible: false
+		})
+	}
+	
+	// a trailing `[1]`, `[ANYSIZE_ARRAY]` or `[]` array is really variable length
+	fn mark_flexible(&mut self) {
+		let flexible = match self.items().pop() {
+			Some(Item::Field(field)) if field.bits.is_none() =>match field.ty {
+				FieldType::Array(_, cnt) =>cnt <= 1,
+				_ =>false
+			},
+			_ =>false
+		};
+		if flexible {
+			self.layout.fields.last_mut().unwrap().flexible = true
+		}
 	}
 	
 	fn new()->Struct {
-		Struct(HashMap::new())
+		Struct { layout: Layout::new(), nested: Vec::new(), pack: None, declared: None }
+	}
+	
+	pub fn pack(&self)->Option<usize> {
+		self.pack
 	}
 	
-	fn layout_mut(&mut self)->&mut HashMap<String, (usize, usize)> {
-		&mut self.0
+	pub fn declared_align(&self)->Option<usize> {
+		self.declared
 	}
 	
-	fn layout(&self)->&HashMap<String, (usize, usize)> {
-		&self.0
+	pub fn layout(&self)->&Layout {
+		&self.layout
 	}
 	
 	fn is_empty(&self)->bool {
-		self.layout().is_empty()
+		self.layout.is_empty()
+	}
+	
+	fn end(&self)->usize {
+		self.items().into_iter().fold(0, |acc, item| {
+			let end = match item {
+				Item::Field(field) =>field.offset + field.size,
+				Item::Nested(nested) =>nested.offset + nested.size()
+			};
+			if acc < end { end } else { acc }
+		})
+	}
+	
+	pub fn align(&self)->usize {
+		let natural = self.layout.align();
+		match self.declared {
+			Some(align) if align > natural =>align,
+			_ =>natural
+		}
+	}
+	
+	pub fn size(&self)->usize {
+		align_to(self.end(), self.align())
 	}
 	
-	fn size(&self)->usize {
-		let mut ret = 0;
-		for (_, &(offset, size)) in self.layout().iter() {
-			if ret < offset + size { ret = offset + size }
+	pub fn padding(&self)->Vec<(usize, usize)> {
+		let mut ret = Vec::new();
+		let mut bound = 0;
+		for item in self.items().into_iter() {
+			let (offset, size) = match item {
+				Item::Field(field) =>(field.offset, field.size),
+				Item::Nested(nested) =>(nested.offset, nested.size())
+			};
+			if offset > bound {
+				ret.push((bound, offset - bound))
+			}
+			if let Item::Nested(nested) = item {
+				ret.extend(nested.value.padding().into_iter().map(|(x, size)| (x + offset, size)))
+			}
+			bound = offset + size
+		}
+		if self.size() > bound {
+			ret.push((bound, self.size() - bound))
 		}
 		ret
 	}
@@ -77,18 +491,7 @@ impl Struct {
 
 impl Display for Struct {
 	fn fmt(&self, f: &mut Formatter)->FmtResult {
-		let mut slice = self.layout().iter().fold(Vec::new(), |mut acc, (field_name, &(offset, size))| {
-			acc.push(((offset, size), format!("\t{:32} {};\n", if size == 0 {
-				format!("{:02X} (offset only, size unknown)", offset)
-			} else {
-				format!("{:02X} - {:02X}", offset, offset + size)
-			}, field_name)));
-			acc
-		});
-		slice.sort();
-		write!(f, "{}", slice.into_iter().fold(String::new(), |acc, (_, s)| {
-			acc + &s
-		}))
+		write_layout(f, &self.layout, self.padding())
 	}
 }
 
@@ -99,255 +502,1174 @@ impl Debug for Struct {
 }
 
 #[derive(PartialEq, Clone)]
-struct Union(HashMap<String, (usize, usize)>);
+pub struct Union {
+	layout: Layout,
+	nested: Vec<Nested>,
+	pack: Option<usize>,
+	declared: Option<usize>
+}
 
 impl Union {
-	fn iter(&self)->Iter<String, (usize, usize)> {
+	pub fn iter(&self)->SliceIter<Field> {
+		self.layout.iter()
+	}
+	
+	pub fn nested(&self)->&Vec<Nested> {
+		&self.nested
+	}
+	
+	pub fn items(&self)->Vec<Item> {
+		items(&self.layout, &self.nested)
+	}
+	
+	fn inject(&mut self, name: Option<String>, value: Type, named: bool)->Result<(), String> {
+		let name = name.unwrap_or_else(|| anonymous_name(&self.nested, &value));
+		let nested = Nested { name: name, offset: 0, value: value, named: named };
+		for field in nested_layout(&nested.value).iter().filter(|x| !x.name.is_empty()) {
+			try!(self.layout.insert(Field { name: nested.prefix() + &field.name, .. field.clone() }))
+		}
+		self.nested.push(nested);
+		Ok(())
+	}
+
+	fn insert(&mut self, name: String, ty: FieldType, size: usize, align: usize)->Result<(), String> {
+		self.layout.insert(Field {
+			name: name, ty: ty, offset: 0, size: size, align: cap(align, self.pack), bits: None, comments: Vec::new(), flexible: false
+		})
+	}
+	
+	fn insert_bits(&mut self, name: String, ty: FieldType, size: usize, align: usize, width: usize)->Result<(), String> {
+		if size == 0 {
+			return Err(format!("bitfield {} needs a primitive type", name))
+		}
+		if width > size * 8 {
+			return Err(format!("bitfield {} is wider than its type", name))
+		}
+		if width == 0 {
+			return Ok(())
+		}
+		self.layout.insert(Field {
+			name: name, ty: ty, offset: 0, size: size, align: cap(align, self.pack), bits: Some((0, width)), comments: Vec::new(), flexible: false
+		})
+	}
+	
+	pub fn layout(&self)->&Layout {
+		&self.layout
+	}
+	
+	fn is_empty(&self)->bool {
+		self.layout.is_empty()
+	}
+	
+	fn new()->Union {
+		Union { layout: Layout::new(), nested: Vec::new(), pack: None, declared: None }
+	}
+	
+	pub fn pack(&self)->Option<usize> {
+		self.pack
+	}
+	
+	pub fn declared_align(&self)->Option<usize> {
+		self.declared
+	}
+	
+	fn end(&self)->usize {
+		self.items().into_iter().fold(0, |acc, item| {
+			let end = match item {
+				Item::Field(field) =>field.size,
+				Item::Nested(nested) =>nested.size()
+			};
+			if acc < end { end } else { acc }
+		})
+	}
+	
+	pub fn align(&self)->usize {
+		let natural = self.layout.align();
+		match self.declared {
+			Some(align) if align > natural =>align,
+			_ =>natural
+		}
+	}
+	
+	pub fn size(&self)->usize {
+		align_to(self.end(), self.align())
+	}
+	
+	pub fn padding(&self)->Vec<(usize, usize)> {
+		let mut ret = Vec::new();
+		for item in self.items().into_iter() {
+			if let Item::Nested(nested) = item {
+				ret.extend(nested.value.padding().into_iter())
+			}
+		}
+		ret.sort();
+		if self.size() > self.end() {
+			ret.push((self.end(), self.size() - self.end()))
+		}
+		ret
+	}
+}
+
+impl Display for Union {
+	fn fmt(&self, f: &mut Formatter)->FmtResult {
+		write_layout(f, &self.layout, self.padding())
+	}
+}
+
+impl Debug for Union {
+	fn fmt(&self, f: &mut Formatter)->FmtResult {
+		write!(f, "union {{\n{}}}", self)
+	}
+}
+
+#[derive(PartialEq, Clone)]
+pub struct Enum(Vec<(String, i64)>);
+
+impl Enum {
+	pub fn iter(&self)->SliceIter<(String, i64)> {
 		self.0.iter()
 	}
+}
+
+impl Display for Enum {
+	fn fmt(&self, f: &mut Formatter)->FmtResult {
+		for &(ref name, value) in self.iter() {
+			try!(write!(f, "\t{} = {},\n", name, value))
+		}
+		Ok(())
+	}
+}
+
+impl Debug for Enum {
+	fn fmt(&self, f: &mut Formatter)->FmtResult {
+		write!(f, "enum {{\n{}}}", self)
+	}
+}
+
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub enum TypeName {
+	Normal(String),
+	Struct(String),
+	Union(String),
+	Enum(String),
+	Function(String)
+}
+
+impl Display for TypeName {
+	fn fmt(&self, f: &mut Formatter)->FmtResult {
+		write!(f, "{}", match self {
+			&TypeName::Normal(ref s) =>s.clone(),
+			&TypeName::Struct(ref s) =>format!("{}", s.clone()),
+			&TypeName::Union(ref s)=>format!("{}", s.clone()),
+			&TypeName::Enum(ref s)=>format!("{}", s.clone()),
+			&TypeName::Function(ref s)=>format!("{}", s.clone())
+		})
+	}
+}
+
+#[derive(Clone, PartialEq)]
+pub enum Type {
+	Struct(Struct),
+	Union(Union),
+	Enum(Enum),
+	Function(Function),
+	Primitive(String),
+	Pointer(FieldType),
+	Unknown(TypeName)
+}
+
+impl Type {
+	pub fn size(&self, target: &Target)->usize {
+		match self {
+			&Type::Struct(ref s) =>s.size(),
+			&Type::Union(ref u) =>u.size(),
+			&Type::Enum(_) =>4,
+			&Type::Function(_) =>0,
+			&Type::Primitive(ref name) =>sdk::lookup(name).map_or(0, |x| x.size(target)),
+			&Type::Pointer(_) =>target.pointer_size,
+			&Type::Unknown(_) =>0
+		}
+	}
+	
+	pub fn align(&self, target: &Target)->usize {
+		match self {
+			&Type::Struct(ref s) =>s.align(),
+			&Type::Union(ref u) =>u.align(),
+			&Type::Enum(_) =>target.align(4),
+			&Type::Function(_) =>1,
+			&Type::Primitive(ref name) =>sdk::lookup(name).map_or(1, |x| target.align(x.align(target))),
+			&Type::Pointer(_) =>target.align(target.pointer_size),
+			&Type::Unknown(_) =>1
+		}
+	}
 	
-	fn inject_struct(&mut self, s: Struct)->Result<(), String> {
-		for (k, &v) in s.iter() {
-			if self.layout_mut().insert(k.clone(), v).is_some() {
-				return Err(format!("dup of field name {}", k))
+	fn padding(&self)->Vec<(usize, usize)> {
+		match self {
+			&Type::Struct(ref s) =>s.padding(),
+			&Type::Union(ref u) =>u.padding(),
+			_ =>Vec::new()
+		}
+	}
+}
+
+impl Debug for Type {
+	fn fmt(&self, f: &mut Formatter)->FmtResult {
+		write!(f, "{}", match self {
+			&Type::Struct(ref s) =>format!("{:?}", s),
+			&Type::Union(ref u) =>format!("{:?}", u),
+			&Type::Enum(ref e) =>format!("{:?}", e),
+			&Type::Function(ref func) =>format!("{}", func),
+			&Type::Primitive(ref name) =>name.clone(),
+			&Type::Pointer(ref ty) =>format!("{}", FieldType::Pointer(Box::new(ty.clone()))),
+			&Type::Unknown(ref name) =>format!("{}", name)
+		})
+	}
+}
+
+#[derive(Clone, PartialEq)]
+pub struct GlobalNameSpace(HashMap<TypeName, Type>);
+
+impl GlobalNameSpace {
+	pub fn new()->GlobalNameSpace {
+		GlobalNameSpace(HashMap::new())
+	}
+	
+	fn insert(&mut self, k: TypeName, v: Type)->Option<Type> {
+		self.0.insert(k, v)
+	}
+	
+	// an aggregate defined inside another one, it may have been forward declared already
+	fn define(&mut self, k: TypeName, v: Type)->Result<(), String> {
+		match self.0.insert(k.clone(), v.clone()) {
+			None | Some(Type::Unknown(_)) =>Ok(()),
+			Some(ref old) if *old == v =>Ok(()),
+			Some(old) =>Err(format!("conflict definition of {}, new: {:?}, old: {:?}", k, v, old))
+		}
+	}
+	
+	pub fn iter(&self)->Iter<TypeName, Type> {
+		self.0.iter()
+	}
+	
+	pub fn get(&self, k: &TypeName)->Option<&Type> {
+		self.0.get(k)
+	}
+	
+	pub fn link(&mut self, library: &str) {
+		for (k, v) in self.0.iter_mut() {
+			match (k, v) {
+				(&TypeName::Function(_), &mut Type::Function(ref mut func)) if func.library.is_none() =>{
+					func.library = Some(library.to_string())
+				},
+				_ =>()
+			}
+		}
+	}
+	
+	pub fn merge(&mut self, other: GlobalNameSpace)->Result<(), String> {
+		for (k, v) in other.0.into_iter() {
+			let keep = match (self.get(&k), &v) {
+				(None, _) | (Some(&Type::Unknown(_)), _) =>false,
+				(Some(_), &Type::Unknown(_)) =>true,
+				(Some(old), new) =>if old == new {
+					true
+				} else {
+					return Err(format!("conflict definition of {}, new: {:?}, old: {:?}", k, new, old))
+				}
+			};
+			if !keep {
+				self.insert(k, v);
+			}
+		}
+		Ok(())
+	}
+	
+	fn align_of(&self, ty: &FieldType, target: &Target)->Option<usize> {
+		match ty {
+			&FieldType::Named(ref name) =>match self.get(name) {
+				None | Some(&Type::Unknown(_)) =>None,
+				Some(x) =>Some(x.align(target))
+			},
+			&FieldType::Pointer(_) =>Some(target.align(target.pointer_size)),
+			&FieldType::Array(ref ty, _) | &FieldType::Qualified(ref ty, _) =>self.align_of(ty, target),
+			&FieldType::Primitive(ref name) =>sdk::lookup(name).map(|x| target.align(x.align(target))),
+			&FieldType::Function(_) | &FieldType::AnonymousStruct | &FieldType::AnonymousUnion =>None
+		}
+	}
+	
+	fn size_of(&self, ty: &FieldType, target: &Target)->Option<usize> {
+		match ty {
+			&FieldType::Named(ref name) =>match self.get(name) {
+				None | Some(&Type::Unknown(_)) =>None,
+				Some(x) =>Some(x.size(target))
+			},
+			&FieldType::Pointer(_) =>Some(target.pointer_size),
+			&FieldType::Array(ref ty, cnt) =>self.size_of(ty, target).map(|x| x * cnt),
+			&FieldType::Qualified(ref ty, _) =>self.size_of(ty, target),
+			&FieldType::Primitive(ref name) =>sdk::lookup(name).map(|x| x.size(target)),
+			&FieldType::Function(_) | &FieldType::AnonymousStruct | &FieldType::AnonymousUnion =>None
+		}
+	}
+	
+	fn resolve_struct(&self, s: &Struct, target: &Target)->Struct {
+		let mut ret = Struct { pack: s.pack, declared: s.declared, .. Struct::new() };
+		for item in s.items().into_iter() {
+			match item {
+				Item::Field(field) =>{
+					let size = self.size_of(&field.ty, target).unwrap_or(field.size);
+					let align = self.align_of(&field.ty, target).unwrap_or(field.align);
+					match field.bits {
+						Some((_, width)) =>ret.insert_bits(field.name.clone(), field.ty.clone(), size, align, width),
+						None =>ret.insert(field.name.clone(), field.ty.clone(), size, align)
+					}.unwrap();
+					ret.layout.annotate(&field.name, field.comments.clone())
+				},
+				Item::Nested(nested) =>{
+					ret.inject(Some(nested.name.clone()), self.resolve_type(&nested.value, target), nested.named).unwrap()
+				}
+			}
+		}
+		ret.mark_flexible();
+		ret
+	}
+	
+	fn resolve_union(&self, u: &Union, target: &Target)->Union {
+		let mut ret = Union { pack: u.pack, declared: u.declared, .. Union::new() };
+		for item in u.items().into_iter() {
+			match item {
+				Item::Field(field) =>{
+					let size = self.size_of(&field.ty, target).unwrap_or(field.size);
+					let align = self.align_of(&field.ty, target).unwrap_or(field.align);
+					match field.bits {
+						Some((_, width)) =>ret.insert_bits(field.name.clone(), field.ty.clone(), size, align, width),
+						None =>ret.insert(field.name.clone(), field.ty.clone(), size, align)
+					}.unwrap();
+					ret.layout.annotate(&field.name, field.comments.clone())
+				},
+				Item::Nested(nested) =>{
+					ret.inject(Some(nested.name.clone()), self.resolve_type(&nested.value, target), nested.named).unwrap()
+				}
+			}
+		}
+		ret
+	}
+	
+	fn resolve_type(&self, value: &Type, target: &Target)->Type {
+		match value {
+			&Type::Struct(ref s) =>Type::Struct(self.resolve_struct(s, target)),
+			&Type::Union(ref u) =>Type::Union(self.resolve_union(u, target)),
+			&Type::Unknown(ref name) =>match self.get(name) {
+				None | Some(&Type::Unknown(_)) =>value.clone(),
+				Some(x) =>x.clone()
+			},
+			&Type::Enum(_) | &Type::Function(_) | &Type::Primitive(_) | &Type::Pointer(_) =>value.clone()
+		}
+	}
+	
+	pub fn resolve(&mut self, target: &Target)->Vec<String> {
+		for _ in 0 .. self.0.len() + 1 {
+			let next = GlobalNameSpace(self.iter().map(|(k, v)| (k.clone(), self.resolve_type(v, target))).collect());
+			if next == *self {
+				break
+			}
+			*self = next
+		}
+		let mut ret = Vec::new();
+		for (k, v) in self.iter() {
+			let fields = match v {
+				&Type::Struct(ref s) =>s.iter().collect::<Vec<_>>(),
+				&Type::Union(ref u) =>u.iter().collect::<Vec<_>>(),
+				&Type::Unknown(ref name) =>{
+					ret.push(format!("{}: unknown type {}", k, name));
+					continue
+				},
+				_ =>continue
+			};
+			for field in fields.into_iter().filter(|x| !x.flexible) {
+				if field.size == 0 {
+					ret.push(format!("{}.{}: unknown type {}", k, field.name, field.ty))
+				}
+			}
+		}
+		ret.sort();
+		ret
+	}
+	
+	fn drain(self)->(GlobalNameSpace, ) {
+		(self,)
+	}
+	
+	fn remove(&mut self, v: &TypeName)->Option<Type> {
+		self.0.remove(v)
+	}
+}
+
+impl Debug for GlobalNameSpace {
+	fn fmt(&self, f: &mut Formatter)->FmtResult {
+		for (k, v) in self.iter() {
+			match k {
+				&TypeName::Normal(ref name) =>try!(write!(f, "typedef {:?} {};\n", v, name)),
+				&TypeName::Struct(ref name) =>if let &Type::Struct(ref s) = v {
+					try!(write!(f, "struct {} {{\n{}}};\n", name, s))
+				} else {
+					panic!("internal error, {:?} inside {}", v, name)
+				},
+				&TypeName::Union(ref name) =>if let &Type::Union(ref s) = v {
+					try!(write!(f, "union {} {{\n{}}};\n", name, s))
+				} else {
+					panic!("internal error, {:?} inside {}", v, name)
+				},
+				&TypeName::Enum(ref name) =>match v {
+					&Type::Enum(ref e) =>try!(write!(f, "enum {} {{\n{}}};\n", name, e)),
+					_ =>try!(write!(f, "enum {};\n", name))
+				},
+				&TypeName::Function(ref name) =>if let &Type::Function(ref func) = v {
+					let conv = func.convention.as_ref().map_or(String::new(), |x| format!("{} ", x));
+					try!(write!(f, "{} {}{}({});\n", func.ret, conv, name, func.params()))
+				} else {
+					panic!("internal error, {:?} inside {}", v, name)
+				}
+			}
+		}
+		Ok(())
+	}
+}
+// Maybe better to impl iter for (GlobalNameSpace, ) and impl drain for GlobalNameSpace .. next time
+
+impl Iterator for (GlobalNameSpace, ) {
+	type Item = (TypeName, Type);
+	fn next(&mut self)->Option<(TypeName, Type)> {
+		let clone = self.0.clone();
+		if let Some((k, v)) = clone.iter().next() {
+			let mut clone = self.0.clone();
+			assert!(clone.remove(k).is_some());
+			self.0 = clone;
+			Some((k.clone(), v.clone()))
+		} else {
+			None
+		}
+	}
+}
+
+// first plain declarator after the body at the head of the stream, or the first declarator if all are pointers
+fn body_declarator(reader: &mut TokenStream)->Option<(String, bool)> {
+	let mut idx = 0;
+	let mut depth = 0;
+	loop {
+		match reader.peek_at(idx) {
+			Some(Token::LeftBrace) =>depth += 1,
+			Some(Token::RightBrace) =>depth -= 1,
+			None =>return None,
+			_ =>()
+		}
+		idx += 1;
+		if depth == 0 {
+			break
+		}
+	}
+	let mut plain = true;
+	let mut first = None;
+	loop {
+		match reader.peek_at(idx) {
+			Some(Token::Ident(ref name)) if QUALIFIERS.iter().any(|x| x == name) =>(),
+			Some(Token::Ident(name)) =>if plain {
+				return Some((name, true))
+			} else if first.is_none() {
+				first = Some((name, false))
+			},
+			Some(Token::Pointer) | Some(Token::LeftParen) =>plain = false,
+			Some(Token::Comma) =>plain = true,
+			Some(Token::SemiColon) | None =>return first,
+			_ =>()
+		}
+		idx += 1
+	}
+}
+
+// scope for the aggregates defined inside the body at the head of the stream
+fn inner_scope(reader: &mut TokenStream, scope: &str)->String {
+	match body_declarator(reader) {
+		Some((ref name, _)) if dummy_name(name).is_none() =>if scope.is_empty() { name.clone() } else { format!("{}_{}", scope, name) },
+		_ =>scope.to_string()
+	}
+}
+
+fn anonymous_tail(reader: &mut TokenStream)->Option<Option<String>> {
+	match (reader.peek(), reader.peek_at(1)) {
+		(Some(Token::SemiColon), _) =>{
+			reader.read().unwrap();
+			Some(None)
+		},
+		(Some(Token::Ident(name)), Some(Token::SemiColon)) =>match dummy_name(&name) {
+			Some(x) =>{
+				reader.read().unwrap();
+				reader.read().unwrap();
+				Some(Some(x))
+			},
+			None =>None
+		},
+		_ =>None
+	}
+}
+
+fn parse_factor(reader: &mut TokenStream, scope: &[(String, i64)])->Result<i64, String> {
+	match reader.read() {
+		Some(Token::Number(x)) =>Ok(x as i64),
+		Some(Token::Ident(name)) =>match scope.iter().find(|x| x.0 == name) {
+			Some(&(_, x)) =>Ok(x),
+			None =>match sdk::constant(&name) {
+				Some(x) =>Ok(x as i64),
+				None =>Err(format!("unknown constant {}", name))
+			}
+		},
+		Some(Token::LeftParen) =>{
+			let ret = try!(parse_constant(reader, scope));
+			try!(reader.eat(Token::RightParen));
+			Ok(ret)
+		},
+		Some(Token::Minus) =>try!(parse_factor(reader, scope)).checked_neg().ok_or(format!("constant overflow")),
+		Some(tok) =>Err(format!("unexpected token {:?}", tok)),
+		None =>Err(format!("unexpected EOF"))
+	}
+}
+
+fn parse_term(reader: &mut TokenStream, scope: &[(String, i64)])->Result<i64, String> {
+	let mut ret = try!(parse_factor(reader, scope));
+	loop {
+		match reader.peek() {
+			Some(Token::Pointer) =>{
+				reader.read().unwrap();
+				ret = try!(ret.checked_mul(try!(parse_factor(reader, scope))).ok_or(format!("constant overflow")))
+			},
+			Some(Token::Slash) =>{
+				reader.read().unwrap();
+				let rhs = try!(parse_factor(reader, scope));
+				if rhs == 0 {
+					return Err(format!("division by zero"))
+				}
+				ret = try!(ret.checked_div(rhs).ok_or(format!("constant overflow")))
+			},
+			_ =>return Ok(ret)
+		}
+	}
+}
+
+fn parse_sum(reader: &mut TokenStream, scope: &[(String, i64)])->Result<i64, String> {
+	let mut ret = try!(parse_term(reader, scope));
+	loop {
+		match reader.peek() {
+			Some(Token::Plus) =>{
+				reader.read().unwrap();
+				ret = try!(ret.checked_add(try!(parse_term(reader, scope))).ok_or(format!("constant overflow")))
+			},
+			Some(Token::Minus) =>{
+				reader.read().unwrap();
+				ret = try!(ret.checked_sub(try!(parse_term(reader, scope))).ok_or(format!("constant overflow")))
+			},
+			_ =>return Ok(ret)
+		}
+	}
+}
+
+fn parse_shift(reader: &mut TokenStream, scope: &[(String, i64)])->Result<i64, String> {
+	let mut ret = try!(parse_sum(reader, scope));
+	while reader.peek() == Some(Token::ShiftLeft) {
+		reader.read().unwrap();
+		ret = match try!(parse_sum(reader, scope)) {
+			x if x < 0 || x >= 64 =>return Err(format!("invalid shift count {}", x)),
+			x =>ret << x
+		}
+	}
+	Ok(ret)
+}
+
+fn parse_constant(reader: &mut TokenStream, scope: &[(String, i64)])->Result<i64, String> {
+	let mut ret = try!(parse_shift(reader, scope));
+	while reader.peek() == Some(Token::Or) {
+		reader.read().unwrap();
+		ret |= try!(parse_shift(reader, scope))
+	}
+	Ok(ret)
+}
+
+fn parse_count(reader: &mut TokenStream)->Result<usize, String> {
+	match try!(parse_constant(reader, &[])) {
+		x if x < 0 =>Err(format!("negative constant {}", x)),
+		x =>Ok(x as usize)
+	}
+}
+
+pub fn evaluate(reader: &mut Iterator<Item=char>)->Result<u64, String> {
+	let tokens = try!(Token::parse(reader));
+	let stream = &mut TokenStream::new(tokens);
+	let ret = try!(parse_count(stream));
+	match stream.read() {
+		Some(tok) =>Err(format!("unexpected token {:?}", tok)),
+		None =>Ok(ret as u64)
+	}
+}
+
+fn skip_parens(reader: &mut TokenStream)->Result<Vec<Token>, String> {
+	try!(reader.eat(Token::LeftParen));
+	let mut ret = Vec::new();
+	let mut depth = 0;
+	loop {
+		match reader.read() {
+			Some(Token::RightParen) if depth == 0 =>return Ok(ret),
+			Some(tok) =>{
+				match tok {
+					Token::LeftParen =>depth += 1,
+					Token::RightParen =>depth -= 1,
+					_ =>()
+				}
+				ret.push(tok)
+			},
+			None =>return Err(format!("unexpected EOF"))
+		}
+	}
+}
+
+fn parse_declspec(reader: &mut TokenStream)->Result<Option<usize>, String> {
+	let mut ret = None;
+	while reader.peek() == Some(Token::Ident(format!("__declspec"))) {
+		reader.read().unwrap();
+		let args = try!(skip_parens(reader));
+		if args.len() == 4 && args[0] == Token::Ident(format!("align")) {
+			match args[2] {
+				Token::Number(x) if x.is_power_of_two() =>ret = Some(x as usize),
+				ref x =>return Err(format!("illegal alignment {:?}", x))
+			}
+		}
+	}
+	Ok(ret)
+}
+
+fn parse_pragma(reader: &mut TokenStream, pack: &mut Option<usize>, packs: &mut Vec<Option<usize>>)->Result<(), String> {
+	let args = try!(skip_parens(reader));
+	if args.get(0) != Some(&Token::Ident(format!("pack"))) {
+		return Ok(())
+	}
+	let mut value = None;
+	let mut push = false;
+	for arg in args[1 ..].iter() {
+		match arg {
+			&Token::Ident(ref x) if x == "push" =>push = true,
+			&Token::Ident(ref x) if x == "pop" =>*pack = packs.pop().unwrap_or(None),
+			&Token::Number(x) =>if x.is_power_of_two() && x <= 16 {
+				value = Some(x as usize)
+			} else {
+				return Err(format!("illegal pack value {}", x))
+			},
+			_ =>()
+		}
+	}
+	if push {
+		packs.push(*pack)
+	}
+	if value.is_some() || args[1 ..].iter().all(|x| *x == Token::LeftParen || *x == Token::RightParen) {
+		*pack = value
+	}
+	Ok(())
+}
+
+fn parse_enum(reader: &mut TokenStream)->Result<Enum, String> {
+	try!(reader.eat(Token::LeftBrace));
+	let mut ret = Vec::new();
+	loop {
+		let name = match reader.read() {
+			Some(Token::RightBrace) =>break,
+			Some(Token::Ident(name)) =>name,
+			Some(tok) =>return Err(format!("unexpected token {:?}", tok)),
+			None =>return Err(format!("unexpected EOF"))
+		};
+		if ret.iter().any(|x: &(String, i64)| x.0 == name) {
+			return Err(format!("dup of enumerator {}", name))
+		}
+		let value = if reader.peek() == Some(Token::Equal) {
+			reader.read().unwrap();
+			try!(parse_constant(reader, &ret))
+		} else {
+			try!(ret.last().map_or(Some(0), |x| x.1.checked_add(1)).ok_or(format!("constant overflow")))
+		};
+		ret.push((name, value));
+		match reader.read() {
+			Some(Token::Comma) =>(),
+			Some(Token::RightBrace) =>break,
+			Some(tok) =>return Err(format!("unexpected token {:?}", tok)),
+			None =>return Err(format!("unexpected EOF"))
+		}
+	}
+	Ok(Enum(ret))
+}
+
+fn has_body(reader: &mut TokenStream)->bool {
+	let mut idx = 0;
+	while reader.peek_at(idx) == Some(Token::Ident(format!("__declspec"))) {
+		idx += 1;
+		let mut depth = 0;
+		loop {
+			match reader.peek_at(idx) {
+				Some(Token::LeftParen) =>depth += 1,
+				Some(Token::RightParen) =>depth -= 1,
+				None =>return false,
+				_ =>()
+			}
+			idx += 1;
+			if depth == 0 {
+				break
+			}
+		}
+	}
+	match (reader.peek_at(idx), reader.peek_at(idx + 1)) {
+		(Some(Token::LeftBrace), _) | (Some(Token::Ident(_)), Some(Token::LeftBrace)) =>true,
+		_ =>false
+	}
+}
+
+static QUALIFIERS: [&'static str; 3] = ["const", "volatile", "__unaligned"];
+
+static C_TYPES: [&'static str; 13] = [
+	"signed", "unsigned", "short", "long", "int", "char", "__int8", "__int16", "__int32", "__int64",
+	"float", "double", "wchar_t"
+];
+
+fn parse_qualifiers(reader: &mut TokenStream)->Vec<String> {
+	let mut ret = Vec::new();
+	loop {
+		match reader.peek() {
+			Some(Token::Ident(ref name)) if QUALIFIERS.iter().any(|x| x == name) =>ret.push(name.clone()),
+			_ =>return ret
+		}
+		reader.read().unwrap();
+	}
+}
+
+fn qualify(ty: FieldType, qualifiers: Vec<String>)->FieldType {
+	if qualifiers.is_empty() {
+		ty
+	} else {
+		FieldType::Qualified(Box::new(ty), qualifiers)
+	}
+}
+
+fn pointer(ty: FieldType, reader: &mut TokenStream)->FieldType {
+	qualify(FieldType::Pointer(Box::new(ty)), parse_qualifiers(reader))
+}
+
+// canonical spelling of a C type specifier sequence, e.g. `long unsigned int` => `unsigned long`
+fn c_type(words: &[String])->Result<String, String> {
+	let spelling = words.iter().fold(String::new(), |acc, x| if acc.is_empty() { x.clone() } else { acc + " " + x });
+	let words = words.iter().map(|x| &**x).collect::<Vec<&str>>();
+	let count = |word: &str| words.iter().filter(|&&x| x == word).count();
+	let (signed, unsigned, short, long) = (count("signed"), count("unsigned"), count("short"), count("long"));
+	let bases = words.iter().cloned().filter(|x| !["signed", "unsigned", "short", "long"].contains(x)).collect::<Vec<_>>();
+	if signed + unsigned > 1 || bases.len() > 1 {
+		return Err(format!("illegal type specifier {}", spelling))
+	}
+	let name = match (bases.get(0).cloned(), short, long) {
+		(None, 0, 0) | (Some("int"), 0, 0) =>"int",
+		(None, 1, 0) | (Some("int"), 1, 0) =>"short",
+		(None, 0, 1) | (Some("int"), 0, 1) =>"long",
+		(None, 0, 2) | (Some("int"), 0, 2) =>"long long",
+		(Some("double"), 0, 1) =>"long double",
+		(Some("char"), 0, 0) if signed > 0 =>"signed char",
+		(Some(x), 0, 0) =>x,
+		_ =>return Err(format!("illegal type specifier {}", spelling))
+	};
+	let name = if unsigned > 0 { format!("unsigned {}", name) } else { name.to_string() };
+	match sdk::lookup(&name) {
+		Some(_) =>Ok(name),
+		None =>Err(format!("illegal type specifier {}", spelling))
+	}
+}
+
+fn parse_specifier(token: Token, reader: &mut TokenStream, target: &Target)->Result<(FieldType, usize, usize), String> {
+	let mut token = token;
+	let mut qualifiers = Vec::new();
+	loop {
+		match token {
+			Token::Ident(ref name) if QUALIFIERS.iter().any(|x| x == name) =>qualifiers.push(name.clone()),
+			_ =>break
+		}
+		token = match reader.read() {
+			Some(x) =>x,
+			None =>return Err(format!("unexpected EOF"))
+		}
+	}
+	let (ty, size, align) = match token {
+		Token::Ident(ref name) if C_TYPES.iter().any(|x| x == name) =>{
+			let mut words = vec![name.clone()];
+			loop {
+				match reader.peek() {
+					Some(Token::Ident(ref x)) if C_TYPES.iter().any(|w| w == x) =>words.push(x.clone()),
+					Some(Token::Ident(ref x)) if QUALIFIERS.iter().any(|q| q == x) =>qualifiers.push(x.clone()),
+					_ =>break
+				}
+				reader.read().unwrap();
+			}
+			primitive(&try!(c_type(&words)), target)
+		},
+		Token::Struct | Token::Union | Token::Enum =>{
+			let tag = match reader.read() {
+				Some(Token::Ident(tag)) =>tag,
+				Some(tok) =>return Err(format!("unexpected token {:?}", tok)),
+				None =>return Err(format!("unexpected EOF"))
+			};
+			if reader.peek() == Some(Token::LeftBrace) {
+				return Err(format!("inline definition of {} is not supported here", tag))
+			}
+			match token {
+				Token::Struct =>(FieldType::Named(TypeName::Struct(tag)), 0, 1),
+				Token::Union =>(FieldType::Named(TypeName::Union(tag)), 0, 1),
+				_ =>(FieldType::Named(TypeName::Enum(tag)), 4, target.align(4))
+			}
+		},
+		Token::Ident(ref name) if name == "void" =>primitive("VOID", target),
+		Token::Ident(name) =>match sdk::lookup(&name) {
+			Some(_) =>primitive(&name, target),
+			None =>(FieldType::Named(TypeName::Normal(name)), 0, 1)
+		},
+		Token::DWORD =>primitive("DWORD", target),
+		Token::WORD =>primitive("WORD", target),
+		Token::BYTE =>primitive("BYTE", target),
+		_ =>return Err(format!("unexpected token {:?}", token))
+	};
+	qualifiers.extend(parse_qualifiers(reader).into_iter());
+	Ok((qualify(ty, qualifiers), size, align))
+}
+
+static CONVENTIONS: [&'static str; 13] = [
+	"WINAPI", "WINAPIV", "NTAPI", "CALLBACK", "APIENTRY", "PASCAL", "STDMETHODCALLTYPE",
+	"IMAGEAPI", "WSAAPI", "__stdcall", "__cdecl", "__fastcall", "CDECL"
+];
+
+fn parse_convention(reader: &mut TokenStream)->Option<String> {
+	match reader.peek() {
+		Some(Token::Ident(ref name)) if CONVENTIONS.iter().any(|x| x == name) =>{
+			reader.read().unwrap();
+			Some(name.clone())
+		},
+		_ =>None
+	}
+}
+
+fn parse_params(reader: &mut TokenStream, target: &Target)->Result<Vec<(String, FieldType)>, String> {
+	try!(reader.eat(Token::LeftParen));
+	let mut ret = Vec::new();
+	if reader.peek() == Some(Token::RightParen) {
+		reader.read().unwrap();
+		return Ok(ret)
+	}
+	loop {
+		while reader.peek() == Some(Token::LeftBracket) {
+			while reader.read().map_or(false, |x| x != Token::RightBracket) {}
+		}
+		let token = match reader.read() {
+			Some(x) =>x,
+			None =>return Err(format!("unexpected EOF"))
+		};
+		let void = token == Token::Ident(format!("void")) || token == Token::Ident(format!("VOID"));
+		if void && ret.is_empty() && reader.peek() == Some(Token::RightParen) {
+			reader.read().unwrap();
+			return Ok(ret)
+		}
+		let (mut ty, _, _) = try!(parse_specifier(token, reader, target));
+		while reader.peek() == Some(Token::Pointer) {
+			reader.read().unwrap();
+			ty = pointer(ty, reader)
+		}
+		let name = if reader.peek() == Some(Token::LeftParen) {
+			let (name, func) = try!(parse_function(reader, ty, target));
+			ty = func;
+			name
+		} else {
+			match reader.peek() {
+				Some(Token::Ident(name)) =>{
+					reader.read().unwrap();
+					name
+				},
+				_ =>String::new()
 			}
+		};
+		while reader.peek() == Some(Token::LeftBracket) {
+			while reader.read().map_or(false, |x| x != Token::RightBracket) {}
+			ty = FieldType::Pointer(Box::new(ty))
 		}
-		Ok(())
-	}
-	
-	fn inject_union(&mut self, u: Union)->Result<(), String> {
-		for (k, &v) in u.iter() {
-			if self.layout_mut().insert(k.clone(), v).is_some() {
-				return Err(format!("dup of field name {}", k))
-			}
+		ret.push((name, ty));
+		match reader.read() {
+			Some(Token::Comma) =>(),
+			Some(Token::RightParen) =>return Ok(ret),
+			Some(tok) =>return Err(format!("unexpected token {:?}", tok)),
+			None =>return Err(format!("unexpected EOF"))
 		}
-		Ok(())
 	}
+}
 
-	fn insert(&mut self, name: String, size: usize)->Option<(usize, usize)> {
-		self.layout_mut().insert(name, (0, size))
-	}
-	
-	fn layout_mut(&mut self)->&mut HashMap<String, (usize, usize)> {
-		&mut self.0
-	}
-	
-	fn layout(&self)->&HashMap<String, (usize, usize)> {
-		&self.0
-	}
-	
-	fn is_empty(&self)->bool {
-		self.layout().is_empty()
-	}
-	
-	fn new()->Union {
-		Union(HashMap::new())
+fn parse_function(reader: &mut TokenStream, ret: FieldType, target: &Target)->Result<(String, FieldType), String> {
+	try!(reader.eat(Token::LeftParen));
+	let convention = parse_convention(reader);
+	let mut depth = 0;
+	while reader.peek() == Some(Token::Pointer) {
+		reader.read().unwrap();
+		parse_qualifiers(reader);
+		depth += 1
 	}
-	
-	fn size(&self)->usize {
-		let mut ret = 0;
-		for (_, &(offset, size)) in self.layout().iter() {
-			if ret < offset + size { ret = offset + size }
-		}
-		ret
+	let name = match reader.peek() {
+		Some(Token::Ident(name)) =>{
+			reader.read().unwrap();
+			name
+		},
+		_ =>String::new()
+	};
+	try!(reader.eat(Token::RightParen));
+	let params = try!(parse_params(reader, target));
+	let mut ty = FieldType::Function(Box::new(Function { ret: ret, convention: convention, params: params, library: None }));
+	for _ in 0 .. depth {
+		ty = FieldType::Pointer(Box::new(ty))
 	}
+	Ok((name, ty))
 }
 
-impl Display for Union {
-	fn fmt(&self, f: &mut Formatter)->FmtResult {
-		let mut slice = self.layout().iter().fold(Vec::new(), |mut acc, (field_name, &(offset, size))| {
-			acc.push(((offset, size), format!("\t{:32} {};\n", if size == 0 {
-				format!("{:02X} (offset only, size unknown)", offset)
-			} else {
-				format!("{:02X} - {:02X}", offset, offset + size)
-			}, field_name)));
-			acc
-		});
-		slice.sort();
-		write!(f, "{}", slice.into_iter().fold(String::new(), |acc, (_, s)| {
-			acc + &s
-		}))
-	}
-}
+static LINKAGE: [&'static str; 8] = [
+	"extern", "DECLSPEC_IMPORT", "WINBASEAPI", "WINADVAPI", "WINUSERAPI", "WINGDIAPI", "NTSYSAPI", "NTSYSCALLAPI"
+];
 
-impl Debug for Union {
-	fn fmt(&self, f: &mut Formatter)->FmtResult {
-		write!(f, "union {{\n{}}}", self)
+fn parse_prototype(token: Token, reader: &mut TokenStream, target: &Target)->Result<(String, Function), String> {
+	let mut token = token;
+	loop {
+		match token {
+			Token::Ident(ref name) if name == "__declspec" =>{
+				try!(skip_parens(reader));
+			},
+			Token::Ident(ref name) if LINKAGE.iter().any(|x| x == name) =>(),
+			_ =>break
+		}
+		token = match reader.read() {
+			Some(x) =>x,
+			None =>return Err(format!("unexpected EOF"))
+		}
 	}
+	let (mut ret, _, _) = try!(parse_specifier(token, reader, target));
+	while reader.peek() == Some(Token::Pointer) {
+		reader.read().unwrap();
+		ret = pointer(ret, reader)
+	}
+	let convention = parse_convention(reader);
+	let name = match reader.read() {
+		Some(Token::Ident(name)) =>name,
+		Some(tok) =>return Err(format!("unexpected token {:?}", tok)),
+		None =>return Err(format!("unexpected EOF"))
+	};
+	let params = try!(parse_params(reader, target));
+	Ok((name, Function { ret: ret, convention: convention, params: params, library: None }))
 }
 
-#[derive(PartialEq, Eq, Clone, Hash)]
-enum TypeName {
-	Normal(String),
-	Struct(String),
-	Union(String)
-}
+static ANNOTATIONS: [&'static str; 19] = [
+	"_In_", "_Out_", "_Inout_", "_Outptr_", "_Ret_", "_Success_", "_Check_return_", "_Must_inspect_result_",
+	"_Reserved_", "_Frees_ptr_", "_Pre_", "_Post_", "_When_", "_Field_", "_Deref_", "_Null_terminated_",
+	"_Printf_format_string_", "_Use_decl_annotations_", "_Return_type_success_"
+];
 
-impl Display for TypeName {
-	fn fmt(&self, f: &mut Formatter)->FmtResult {
-		write!(f, "{}", match self {
-			&TypeName::Normal(ref s) =>s.clone(),
-			&TypeName::Struct(ref s) =>format!("{}", s.clone()),
-			&TypeName::Union(ref s)=>format!("{}", s.clone())
-		})
-	}
+static LEGACY_ANNOTATIONS: [&'static str; 8] = [
+	"__in", "__out", "__inout", "__deref", "__reserved", "__checkReturn", "__success", "__field"
+];
+
+fn is_annotation(name: &str)->bool {
+	ANNOTATIONS.iter().any(|x| name.starts_with(x)) ||
+		LEGACY_ANNOTATIONS.iter().any(|x| name == *x || name.starts_with(&format!("{}_", x)))
 }
 
-#[derive(Clone, PartialEq)]
-enum Type {
-	Struct(Struct),
-	Union(Union),
-	Primitive(usize),
-	Pointer(Rc<Type>),
-	Unknown(TypeName)
+// SAL annotations carry no layout information, drop them together with their arguments
+fn strip_annotations(tokens: Vec<(Token, usize)>)->Vec<(Token, usize)> {
+	let mut ret = Vec::new();
+	let mut iter = tokens.into_iter().peekable();
+	while let Some(tok) = iter.next() {
+		match tok.0 {
+			Token::Ident(ref name) if is_annotation(name) =>if iter.peek().map(|x| &x.0) == Some(&Token::LeftParen) {
+				let mut depth = 0;
+				for (tok, _) in iter.by_ref() {
+					match tok {
+						Token::LeftParen =>depth += 1,
+						Token::RightParen =>depth -= 1,
+						_ =>()
+					}
+					if depth == 0 {
+						break
+					}
+				}
+			},
+			_ =>ret.push(tok)
+		}
+	}
+	ret
 }
 
-impl Debug for Type {
-	fn fmt(&self, f: &mut Formatter)->FmtResult {
-		write!(f, "{}", match self {
-			&Type::Struct(ref s) =>format!("{:?}", s),
-			&Type::Union(ref u) =>format!("{:?}", u),
-			&Type::Primitive(size) =>format!("primitive[size: {}]", size),
-			&Type::Pointer(ref rc) =>format!("{:?}*", &rc),
-			&Type::Unknown(ref name) =>format!("{}", name)
-		})
+fn declarator_type(ty: FieldType)->Type {
+	match ty {
+		FieldType::Qualified(inner, _) =>declarator_type(*inner),
+		FieldType::Pointer(inner) =>Type::Pointer(*inner),
+		FieldType::Function(func) =>Type::Function(*func),
+		_ =>panic!("internal error, {} is not a pointer or function type", ty)
 	}
 }
 
-#[derive(Clone, PartialEq)]
-struct GlobalNameSpace(HashMap<TypeName, Type>);
+enum Member {
+	End,
+	Anonymous(Option<String>, Type),
+	Nested(String, Type),
+	Fields(Vec<(String, FieldType, usize, usize, Option<usize>)>, Vec<String>)
+}
 
-impl GlobalNameSpace {
-	fn new()->GlobalNameSpace {
-		GlobalNameSpace(HashMap::new())
-	}
-	
-	fn insert(&mut self, k: TypeName, v: Type)->Option<Type> {
-		self.0.insert(k, v)
-	}
-	
-	fn iter(&self)->Iter<TypeName, Type> {
-		self.0.iter()
-	}
-	
-	fn drain(self)->(GlobalNameSpace, ) {
-		(self,)
-	}
-	
-	fn remove(&mut self, v: &TypeName)->Option<Type> {
-		self.0.remove(v)
-	}
+fn primitive(name: &str, target: &Target)->(FieldType, usize, usize) {
+	let base = sdk::lookup(name).unwrap();
+	(FieldType::Primitive(name.to_string()), base.size(target), target.align(base.align(target)))
 }
 
-impl Debug for GlobalNameSpace {
-	fn fmt(&self, f: &mut Formatter)->FmtResult {
-		for (k, v) in self.iter() {
-			match k {
-				&TypeName::Normal(ref name) =>try!(write!(f, "typedef {:?} {};\n", v, name)),
-				&TypeName::Struct(ref name) =>if let &Type::Struct(ref s) = v {
-					try!(write!(f, "struct {} {{\n{}}};\n", name, s))
-				} else {
-					panic!("internal error, {:?} inside {}", v, name)
+fn parse_member(reader: &mut TokenStream, target: &Target, pack: Option<usize>, scope: &str, ns: &mut GlobalNameSpace)
+	->Result<Member, String> {
+	let mut comments = reader.comments(false);
+	let token = match reader.read() {
+		None =>return Err(format!("unexpected EOF")),
+		Some(x) =>x
+	};
+	let (ty, size, align) = match token {
+		Token::RightBrace =>return Ok(Member::End),
+		Token::Struct | Token::Union if has_body(reader) =>{
+			let is_union = token == Token::Union;
+			let declared = try!(parse_declspec(reader));
+			let tag = match reader.peek() {
+				Some(Token::Ident(name)) =>{
+					reader.read().unwrap();
+					Some(name)
 				},
-				&TypeName::Union(ref name) =>if let &Type::Union(ref s) = v {
-					try!(write!(f, "union {} {{\n{}}};\n", name, s))
-				} else {
-					panic!("internal error, {:?} inside {}", v, name)
-				}
+				_ =>None
+			};
+			match (tag, reader.peek()) {
+				(tag, Some(Token::LeftBrace)) =>{
+					let inner = match tag {
+						Some(ref tag) =>tag.clone(),
+						None =>inner_scope(reader, scope)
+					};
+					let value = if is_union {
+						Type::Union(try!(parse_union(reader, target, pack, declared, &inner, ns)))
+					} else {
+						Type::Struct(try!(parse_struct(reader, target, pack, declared, &inner, ns)))
+					};
+					// a tag is visible outside of the enclosing aggregate, an untagged body used by
+					// several declarators gets the name the generator would give a nested member
+					let name = match (tag, is_union) {
+						(Some(tag), false) =>TypeName::Struct(tag),
+						(Some(tag), true) =>TypeName::Union(tag),
+						(None, _) =>{
+							if let Some(name) = anonymous_tail(reader) {
+								return Ok(Member::Anonymous(name, value))
+							}
+							if let (Some(Token::Ident(name)), Some(Token::SemiColon)) = (reader.peek(), reader.peek_at(1)) {
+								reader.read().unwrap();
+								reader.read().unwrap();
+								return Ok(Member::Nested(name, value))
+							}
+							if is_union { TypeName::Union(inner) } else { TypeName::Struct(inner) }
+						}
+					};
+					try!(ns.define(name.clone(), value.clone()));
+					if let Some(member) = anonymous_tail(reader) {
+						return Ok(Member::Anonymous(member, value))
+					}
+					(FieldType::Named(name), aggregate_size(&value), aggregate_align(&value))
+				},
+				(_, Some(x)) =>return Err(format!("unexpected token {:?}", x)),
+				(_, None) =>return Err(format!("unexpected EOF"))
 			}
+		},
+		token =>try!(parse_specifier(token, reader, target))
+	};
+	let mut fields = Vec::new();
+	loop {
+		fields.push(try!(parse_declarator(reader, target, ty.clone(), size, align)));
+		match reader.read() {
+			Some(Token::Comma) =>(),
+			Some(Token::SemiColon) =>break,
+			Some(tok) =>return Err(format!("unexpected token {:?}", tok)),
+			None =>return Err(format!("unexpected EOF"))
 		}
-		Ok(())
 	}
+	comments.extend(reader.comments(true).into_iter());
+	Ok(Member::Fields(fields, comments))
 }
-// Maybe better to impl iter for (GlobalNameSpace, ) and impl drain for GlobalNameSpace .. next time
 
-impl Iterator for (GlobalNameSpace, ) {
-	type Item = (TypeName, Type);
-	fn next(&mut self)->Option<(TypeName, Type)> {
-		let clone = self.0.clone();
-		if let Some((k, v)) = clone.iter().next() {
-			let mut clone = self.0.clone();
-			assert!(clone.remove(k).is_some());
-			self.0 = clone;
-			Some((k.clone(), v.clone()))
-		} else {
-			None
+fn parse_declarator(reader: &mut TokenStream, target: &Target, ty: FieldType, size: usize, align: usize)
+	->Result<(String, FieldType, usize, usize, Option<usize>), String> {
+	let mut ty = ty;
+	let mut size = size;
+	let mut align = align;
+	while reader.peek() == Some(Token::Pointer) {
+		reader.read().unwrap();
+		ty = pointer(ty, reader);
+		size = target.pointer_size;
+		align = target.align(target.pointer_size)
+	}
+	let name = if reader.peek() == Some(Token::LeftParen) {
+		let (name, func) = try!(parse_function(reader, ty, target));
+		match func {
+			FieldType::Pointer(_) =>(),
+			_ =>return Err(format!("member {} is a function, not a function pointer", name))
+		}
+		ty = func;
+		size = target.pointer_size;
+		align = target.align(target.pointer_size);
+		name
+	} else {
+		match reader.peek() {
+			Some(Token::Colon) =>String::new(),
+			Some(Token::Ident(name)) =>{
+				reader.read().unwrap();
+				name
+			},
+			Some(tok) =>return Err(format!("unexpected token {:?}", tok)),
+			None =>return Err(format!("unexpected EOF"))
 		}
+	};
+	let mut dims = Vec::new();
+	while reader.peek() == Some(Token::LeftBracket) {
+		reader.read().unwrap();
+		dims.push(if reader.peek() == Some(Token::RightBracket) { 0 } else { try!(parse_count(reader)) });
+		try!(reader.eat(Token::RightBracket))
 	}
+	for &cnt in dims.iter().rev() {
+		ty = FieldType::Array(Box::new(ty), cnt);
+		size *= cnt
+	}
+	let width = if reader.peek() == Some(Token::Colon) {
+		reader.read().unwrap();
+		Some(try!(parse_count(reader)))
+	} else if name.is_empty() {
+		return Err(format!("unexpected token {:?}", Token::Colon))
+	} else {
+		None
+	};
+	Ok((name, ty, size, align, width))
 }
 
-fn make_pointer(v: Type)->Type {
-	Type::Pointer(Rc::new(v))
-}
-
-fn parse_struct(reader: &mut TokenStream)->Result<Struct, String> {
+fn parse_struct(reader: &mut TokenStream, target: &Target, pack: Option<usize>, declared: Option<usize>,
+	scope: &str, ns: &mut GlobalNameSpace)->Result<Struct, String> {
 	try!(reader.eat(Token::LeftBrace));
-	let mut ret = Struct::new();
+	let mut ret = Struct { pack: pack, declared: declared, .. Struct::new() };
 	loop {
-		let token = match reader.read() {
-			None =>return Err(format!("unexpected EOF")),
-			Some(x) =>x
-		};
-		let size = match token {
-			Token::RightBrace =>break,
-			Token::Struct =>{
-				let peek = reader.peek();
-				if let Some(Token::Ident(_)) = peek {
-					reader.read().unwrap();
-				}
-				let s = try!(parse_struct(reader));
-				let peek = reader.peek();
-				if peek == Some(Token::SemiColon) {
-					reader.read().unwrap();
-					try!(ret.inject_struct(s));
-					continue
-				} else {
-					s.size()
-				}
-			},
-			Token::Union =>{
-				let peek = reader.peek();
-				if let Some(Token::Ident(_)) = peek {
-					reader.read().unwrap();
-				}
-				let u = try!(parse_union(reader));
-				let peek = reader.peek();
-				if peek == Some(Token::SemiColon) {
-					reader.read().unwrap();
-					try!(ret.inject_union(u));
-					continue
-				} else {
-					u.size()
-				}
-			},
-			Token::Ident(_) =>0,
-			Token::DWORD =>4,
-			Token::WORD =>2,
-			Token::BYTE =>1,
-			Token::SemiColon | Token::Comma | Token::LeftBrace | Token::Typedef | Token::Pointer =>{
-				return Err(format!("unexpected token {:?}", token))
-			}
-		};
-		let mut size = size;
-		loop {
-			let peek = reader.peek();
-			if Some(Token::Pointer) == peek {
-				reader.read().unwrap();
-				size = POINTER_SIZE;
-			} else {
-				break
+		match try!(parse_member(reader, target, pack, scope, ns)) {
+			Member::End =>break,
+			Member::Anonymous(name, value) =>try!(ret.inject(name, value, false)),
+			Member::Nested(name, value) =>try!(ret.inject(Some(name), value, true)),
+			Member::Fields(fields, comments) =>for (name, ty, size, align, width) in fields.into_iter() {
+				try!(match width {
+					Some(width) =>ret.insert_bits(name.clone(), ty, size, align, width),
+					None =>ret.insert(name.clone(), ty, size, align)
+				});
+				ret.layout.annotate(&name, comments.clone())
 			}
 		}
-		let size = size;
-		match reader.read() {
-			Some(Token::Ident(name)) =>if ret.insert(name.clone(), size).is_some() {
-				return Err(format!("dup of field name {}", name))
-			},
-			Some(tok) =>return Err(format!("unexpected token {:?}", tok)),
-			None =>return Err(format!("unexpected EOF"))
-		}
-		try!(reader.eat(Token::SemiColon))
 	}
+	ret.mark_flexible();
 	if ret.is_empty() {
 		Err(format!("a struct needs at least one field"))
 	} else {
@@ -355,73 +1677,23 @@ fn parse_struct(reader: &mut TokenStream)->Result<Struct, String> {
 	}
 }
 
-fn parse_union(reader: &mut TokenStream)->Result<Union, String> {
+fn parse_union(reader: &mut TokenStream, target: &Target, pack: Option<usize>, declared: Option<usize>,
+	scope: &str, ns: &mut GlobalNameSpace)->Result<Union, String> {
 	try!(reader.eat(Token::LeftBrace));
-	let mut ret = Union::new();
+	let mut ret = Union { pack: pack, declared: declared, .. Union::new() };
 	loop {
-		let token = match reader.read() {
-			None =>return Err(format!("unexpected EOF")),
-			Some(x) =>x
-		};
-		let val = match token {
-			Token::RightBrace =>break,
-			Token::Struct =>{
-				let peek = reader.peek();
-				if let Some(Token::Ident(_)) = peek {
-					reader.read().unwrap();
-				}
-				let s = try!(parse_struct(reader));
-				let peek = reader.peek();
-				if peek == Some(Token::SemiColon) {
-					reader.read().unwrap();
-					try!(ret.inject_struct(s));
-					continue
-				} else {
-					s.size()
-				}
-			},
-			Token::Union =>{
-				let peek = reader.peek();
-				if let Some(Token::Ident(_)) = peek {
-					reader.read().unwrap();
-				};
-				let u = try!(parse_union(reader));
-				let peek = reader.peek();
-				if peek == Some(Token::SemiColon) {
-					reader.read().unwrap();
-					try!(ret.inject_union(u));
-					continue
-				} else {
-					u.size()
-				}
-			},
-			Token::Ident(_) =>0,
-			Token::DWORD =>4,
-			Token::WORD =>2,
-			Token::BYTE =>1,
-			Token::SemiColon | Token::Comma | Token::LeftBrace | Token::Typedef | Token::Pointer =>{
-				return Err(format!("unexpected token {:?}", token))
-			}
-		};
-		let mut val = val;
-		loop {
-			let peek = reader.peek();
-			if Some(Token::Pointer) == peek {
-				reader.read().unwrap();
-				val = POINTER_SIZE;
-			} else {
-				break
+		match try!(parse_member(reader, target, pack, scope, ns)) {
+			Member::End =>break,
+			Member::Anonymous(name, value) =>try!(ret.inject(name, value, false)),
+			Member::Nested(name, value) =>try!(ret.inject(Some(name), value, true)),
+			Member::Fields(fields, comments) =>for (name, ty, size, align, width) in fields.into_iter() {
+				try!(match width {
+					Some(width) =>ret.insert_bits(name.clone(), ty, size, align, width),
+					None =>ret.insert(name.clone(), ty, size, align)
+				});
+				ret.layout.annotate(&name, comments.clone())
 			}
 		}
-		let val = val;
-		match reader.read() {
-			Some(Token::Ident(name)) =>if ret.insert(name.clone(), val).is_some() {
-				return Err(format!("dup of field name {}", name))
-			},
-			Some(tok) =>return Err(format!("unexpected token {:?}", tok)),
-			None =>return Err(format!("unexpected EOF"))
-		}
-		try!(reader.eat(Token::SemiColon))
 	}
 	if ret.is_empty() {
 		Err(format!("a union needs at least one field"))
@@ -431,62 +1703,143 @@ fn parse_union(reader: &mut TokenStream)->Result<Union, String> {
 }
 
 	
-fn parse_typedef(reader: &mut TokenStream)->Result<GlobalNameSpace, String> {
+fn parse_typedef(reader: &mut TokenStream, target: &Target, pack: Option<usize>, ns: &mut GlobalNameSpace)
+	->Result<GlobalNameSpace, String> {
 	let token = match reader.read() {
 		None =>return Err(format!("unexpected EOF")),
 		Some(x) =>x
 	};
 	let mut optional_name = None;
+	let mut base = None;
 	let val = match token {
 		Token::Struct =>{
+			let declared = try!(parse_declspec(reader));
 			let peek = reader.peek();
 			if let Some(Token::Ident(name)) = peek {
 				reader.read().unwrap();
 				optional_name = Some(TypeName::Struct(name.clone()))
 			}
-			Type::Struct(try!(parse_struct(reader)))
+			match (optional_name.take(), reader.peek()) {
+				(Some(tag), Some(ref tok)) if *tok != Token::LeftBrace =>{
+					base = Some(FieldType::Named(tag.clone()));
+					Type::Unknown(tag)
+				},
+				(tag, _) =>{
+					let (scope, tag) = match (tag, body_declarator(reader)) {
+						(Some(tag), _) =>(format!("{}", tag), Some(tag)),
+						(None, Some((name, true))) =>{
+							base = Some(FieldType::Named(TypeName::Normal(name.clone())));
+							(name, None)
+						},
+						(None, Some((name, false))) =>(name.clone(), Some(TypeName::Struct(format!("_{}", name)))),
+						(None, None) =>(String::new(), None)
+					};
+					optional_name = tag;
+					Type::Struct(try!(parse_struct(reader, target, pack, declared, &scope, ns)))
+				}
+			}
 		},
 		Token::Union =>{
+			let declared = try!(parse_declspec(reader));
 			let peek = reader.peek();
 			if let Some(Token::Ident(name)) = peek {
 				reader.read().unwrap();
 				optional_name = Some(TypeName::Union(name.clone()))
 			}
-			Type::Union(try!(parse_union(reader)))
+			match (optional_name.take(), reader.peek()) {
+				(Some(tag), Some(ref tok)) if *tok != Token::LeftBrace =>{
+					base = Some(FieldType::Named(tag.clone()));
+					Type::Unknown(tag)
+				},
+				(tag, _) =>{
+					let (scope, tag) = match (tag, body_declarator(reader)) {
+						(Some(tag), _) =>(format!("{}", tag), Some(tag)),
+						(None, Some((name, true))) =>{
+							base = Some(FieldType::Named(TypeName::Normal(name.clone())));
+							(name, None)
+						},
+						(None, Some((name, false))) =>(name.clone(), Some(TypeName::Union(format!("_{}", name)))),
+						(None, None) =>(String::new(), None)
+					};
+					optional_name = tag;
+					Type::Union(try!(parse_union(reader, target, pack, declared, &scope, ns)))
+				}
+			}
 		},
-		Token::DWORD =>Type::Primitive(4),
-		Token::WORD =>Type::Primitive(2),
-		Token::BYTE =>Type::Primitive(1),
-		Token::SemiColon | Token::Comma | Token::LeftBrace | Token::RightBrace |
-			Token::Typedef | Token::Pointer =>{
-			return Err(format!("unexpected token {:?}", token))
+		Token::Enum =>{
+			let tag = match reader.peek() {
+				Some(Token::Ident(name)) =>{
+					reader.read().unwrap();
+					Some(TypeName::Enum(name))
+				},
+				_ =>None
+			};
+			match (tag, reader.peek()) {
+				(tag, Some(Token::LeftBrace)) =>{
+					optional_name = tag;
+					Type::Enum(try!(parse_enum(reader)))
+				},
+				(Some(tag), _) =>{
+					base = Some(FieldType::Named(tag.clone()));
+					Type::Unknown(tag)
+				},
+				(None, Some(tok)) =>return Err(format!("unexpected token {:?}", tok)),
+				(None, None) =>return Err(format!("unexpected EOF"))
+			}
 		},
-		Token::Ident(name) =>Type::Unknown(TypeName::Normal(name))
+		token =>{
+			let (ty, _, _) = try!(parse_specifier(token, reader, target));
+			let val = match *ty.unqualified() {
+				FieldType::Named(ref name) =>Type::Unknown(name.clone()),
+				FieldType::Primitive(ref name) =>Type::Primitive(name.clone()),
+				ref x =>return Err(format!("unexpected type {}", x))
+			};
+			base = Some(ty);
+			val
+		}
 	};
 	let mut ret = GlobalNameSpace::new();
 	if let Some(name) = optional_name {
+		if base.is_none() {
+			base = Some(FieldType::Named(name.clone()))
+		}
 		assert_eq!(ret.insert(name, val.clone()), None)
 	}
 	loop {
-		let mut val = val.clone();
-		loop {
-			let peek = reader.peek();
-			if peek == Some(Token::Pointer) {
-				reader.read().unwrap();
-				val = make_pointer(val);
-			} else {
-				break
-			}
+		// pointers refer to their pointee by name, an untagged aggregate is named after its first plain declarator
+		// or gets the tag `_NAME` when it is only declared through pointers
+		let mut ty = match base {
+			Some(ref x) =>x.clone(),
+			None =>return Err(format!("untagged aggregate without a declarator"))
+		};
+		let mut is_pointer = false;
+		while reader.peek() == Some(Token::Pointer) {
+			reader.read().unwrap();
+			ty = pointer(ty, reader);
+			is_pointer = true
 		}
-		let val = val;
-		match reader.read() {
-			Some(Token::Ident(name)) =>{
-				if ret.insert(TypeName::Normal(name.clone()), val).is_some() {
-					return Err(format!("dup of typedef name {}", name))
-				}
-			},
-			Some(x) =>return Err(format!("unexpected token {:?}", x)),
-			None =>return Err(format!("unexpected EOF"))
+		let (name, val) = if reader.peek() == Some(Token::LeftParen) {
+			let (name, func) = try!(parse_function(reader, ty, target));
+			(name, declarator_type(func))
+		} else {
+			let convention = parse_convention(reader);
+			let name = match reader.read() {
+				Some(Token::Ident(name)) =>name,
+				Some(x) =>return Err(format!("unexpected token {:?}", x)),
+				None =>return Err(format!("unexpected EOF"))
+			};
+			match reader.peek() {
+				Some(Token::LeftParen) =>{
+					let params = try!(parse_params(reader, target));
+					(name, Type::Function(Function { ret: ty, convention: convention, params: params, library: None }))
+				},
+				_ if convention.is_some() =>return Err(format!("calling convention on non-function {}", name)),
+				_ if is_pointer =>(name, declarator_type(ty)),
+				_ =>(name, val.clone())
+			}
+		};
+		if ret.insert(TypeName::Normal(name.clone()), val).is_some() {
+			return Err(format!("dup of typedef name {}", name))
 		}
 		match reader.peek() {
 			Some(Token::Comma) =>(),
@@ -498,22 +1851,29 @@ fn parse_typedef(reader: &mut TokenStream)->Result<GlobalNameSpace, String> {
 	}
 }
 
-pub fn compile(reader: &mut Iterator<Item=char>)->Result<GlobalNameSpace, String> {
-	let tokens = try!(Token::parse(reader));
-	let stream = &mut TokenStream::new(tokens);
+pub fn compile(reader: &mut Iterator<Item=char>, comments: &[Comment], target: &Target)->Result<GlobalNameSpace, String> {
+	let tokens = strip_annotations(try!(Token::parse_lines(reader)));
+	let stream = &mut TokenStream::with_comments(tokens, comments);
 	let mut ret = GlobalNameSpace::new();
+	let mut pack = None;
+	let mut packs = Vec::new();
 	loop {
 		let token = match stream.read() {
 			Some(x) =>x,
 			None =>break
 		};
 		match token {
-			Token::Typedef =>for (k, v) in try!(parse_typedef(stream)).drain() {
+			Token::Ident(ref name) if name == "__pragma" =>{
+				try!(parse_pragma(stream, &mut pack, &mut packs));
+				continue
+			},
+			Token::Typedef =>for (k, v) in try!(parse_typedef(stream, target, pack, &mut ret)).drain() {
 				if ret.insert(k.clone(), v).is_some() {
 					return Err(format!("dup of type name {}", k))
 				}
 			},
 			Token::Struct =>{
+				let declared = try!(parse_declspec(stream));
 				let peek = stream.peek();
 				if let Some(Token::Ident(name)) = peek {
 					stream.read().unwrap();
@@ -531,8 +1891,8 @@ pub fn compile(reader: &mut Iterator<Item=char>)->Result<GlobalNameSpace, String
 							None =>()
 						}
 					} else {
-						let struct_name = TypeName::Struct(name);
-						let val = Type::Struct(try!(parse_struct(stream)));
+						let struct_name = TypeName::Struct(name.clone());
+						let val = Type::Struct(try!(parse_struct(stream, target, pack, declared, &name, &mut ret)));
 						match ret.insert(struct_name.clone(), val.clone()) {
 							None | Some(Type::Unknown(TypeName::Struct(_))) =>(),
 							x @ Some(Type::Struct(_)) =>if x != Some(val.clone()) {
@@ -544,10 +1904,11 @@ pub fn compile(reader: &mut Iterator<Item=char>)->Result<GlobalNameSpace, String
 						}
 					}
 				} else {
-					try!(parse_struct(stream));
+					try!(parse_struct(stream, target, pack, declared, "", &mut ret));
 				}
 			},
 			Token::Union =>{
+				let declared = try!(parse_declspec(stream));
 				let peek = stream.peek();
 				if let Some(Token::Ident(name)) = peek {
 					stream.read().unwrap();
@@ -566,7 +1927,7 @@ pub fn compile(reader: &mut Iterator<Item=char>)->Result<GlobalNameSpace, String
 						}
 					} else {
 						let union_name = TypeName::Union(name.clone());
-						let val = Type::Union(try!(parse_union(stream)));
+						let val = Type::Union(try!(parse_union(stream, target, pack, declared, &name, &mut ret)));
 						match ret.insert(union_name.clone(), val.clone()) {
 							None | Some(Type::Unknown(TypeName::Union(_))) =>(),
 							x @ Some(Type::Union(_)) =>if x != Some(val.clone()) {
@@ -578,12 +1939,46 @@ pub fn compile(reader: &mut Iterator<Item=char>)->Result<GlobalNameSpace, String
 						}
 					}
 				} else {
-					try!(parse_union(stream));
+					try!(parse_union(stream, target, pack, declared, "", &mut ret));
+				}
+			},
+			Token::Enum =>{
+				let tag = match stream.peek() {
+					Some(Token::Ident(name)) =>{
+						stream.read().unwrap();
+						Some(TypeName::Enum(name))
+					},
+					Some(Token::LeftBrace) =>None,
+					Some(tok) =>return Err(format!("unexpected token {:?}", tok)),
+					None =>return Err(format!("unexpected EOF"))
+				};
+				match tag {
+					Some(tag) =>if stream.peek() == Some(Token::LeftBrace) {
+						let val = Type::Enum(try!(parse_enum(stream)));
+						match ret.insert(tag.clone(), val.clone()) {
+							None | Some(Type::Unknown(_)) =>(),
+							Some(old) =>if old != val {
+								return Err(format!("conflict definition of {}, new: {:?}, old: {:?}", tag, val, old))
+							}
+						}
+					} else if ret.get(&tag).is_none() {
+						ret.insert(tag.clone(), Type::Unknown(tag));
+					},
+					None =>{
+						try!(parse_enum(stream));
+					}
 				}
 			},
-			Token::SemiColon | Token::Comma | Token::LeftBrace | Token::RightBrace |
-				Token::Pointer | Token::Ident(_) | Token::DWORD | Token::WORD | Token::BYTE =>{
-				return Err(format!("unexpected token {:?}", token))
+			token =>{
+				let (name, func) = try!(parse_prototype(token, stream, target));
+				let name = TypeName::Function(name);
+				let val = Type::Function(func);
+				match ret.insert(name.clone(), val.clone()) {
+					Some(ref old) if *old != val =>{
+						return Err(format!("conflict definition of {}, new: {:?}, old: {:?}", name, val, old))
+					},
+					_ =>()
+				}
 			}
 		}
 		try!(stream.eat(Token::SemiColon))
@@ -1,8 +1,10 @@
 #[test]
 fn test_simple_case_for_parser() {
+	use target;
 	use super::{
 		TypeName,
 		Type,
+		FieldType,
 		compile,
 		Struct,
 		Union,
@@ -13,7 +15,7 @@ fn test_simple_case_for_parser() {
 			"typedef DWORD u32;",
 			{
 				let mut ns = GlobalNameSpace::new();
-				ns.insert(TypeName::Normal(format!("u32")), Type::Primitive(4));
+				ns.insert(TypeName::Normal(format!("u32")), Type::Primitive(format!("DWORD")));
 				ns
 			}
 		),
@@ -25,7 +27,7 @@ fn test_simple_case_for_parser() {
 			{
 				let mut ns = GlobalNameSpace::new();
 				let mut s = Struct::new();
-				s.insert(format!("b"), 1);
+				s.insert(format!("b"), FieldType::Primitive(format!("BYTE")), 1, 1).unwrap();
 				let s = Type::Struct(s);
 				ns.insert(TypeName::Struct(format!("s")), s);
 				ns
@@ -35,7 +37,7 @@ fn test_simple_case_for_parser() {
 			"typedef struct _s { DWORD val; } s;",
 			{
 				let mut structure = Struct::new();
-				structure.insert(format!("val"), 4);
+				structure.insert(format!("val"), FieldType::Primitive(format!("DWORD")), 4, 4).unwrap();
 				let mut ns = GlobalNameSpace::new();
 				ns.insert(TypeName::Struct(format!("_s")), Type::Struct(structure.clone()));
 				ns.insert(TypeName::Normal(format!("s")), Type::Struct(structure));
@@ -46,10 +48,10 @@ fn test_simple_case_for_parser() {
 			"typedef struct { union { DWORD val; WORD word; }; } s;",
 			{
 				let mut u = Union::new();
-				u.insert(format!("val"), 4);
-				u.insert(format!("word"), 2);
+				u.insert(format!("val"), FieldType::Primitive(format!("DWORD")), 4, 4).unwrap();
+				u.insert(format!("word"), FieldType::Primitive(format!("WORD")), 2, 2).unwrap();
 				let mut s = Struct::new();
-				assert_eq!(s.inject_union(u), Ok(()));
+				assert_eq!(s.inject(None, Type::Union(u), false), Ok(()));
 				let mut ns = GlobalNameSpace::new();
 				ns.insert(TypeName::Normal(format!("s")), Type::Struct(s));
 				ns
@@ -57,17 +59,269 @@ fn test_simple_case_for_parser() {
 		)
 	];
 	for (s, m) in tests.drain() {
-		assert_eq!(compile(&mut s.chars()).unwrap(), m)
+		assert_eq!(compile(&mut s.chars(), &[], target::default()).unwrap(), m)
 	}
 }
 
 #[test]
 fn test_parser_output() {
+	use target;
 	vec![
 		(
-			format!("typedef struct {{\n\t{:32} word;\n\t{:32} val;\n}} s;\n",
-						"00 - 02", "00 - 04"),
-			super::compile(&mut "typedef struct { union { DWORD val; WORD word; }; } s;".chars()).unwrap()
+			format!("typedef struct {{\n\t{:32} DWORD val;\n\t{:32} WORD word;\n}} s;\n",
+						"00 - 04", "00 - 02"),
+			super::compile(&mut "typedef struct { union { DWORD val; WORD word; }; } s;".chars(), &[], target::default()).unwrap()
+		),
+		(
+			format!("struct s {{\n\t{:32} struct s* next;\n}};\n", "00 - 04"),
+			super::compile(&mut "struct s { struct s *next; };".chars(), &[], target::default()).unwrap()
 		)
 	].into_iter().fold((), |_, (lhs, rhs)| assert_eq!(lhs, format!("{:?}", rhs)))
 }
+
+#[test]
+fn test_resolve() {
+	use target;
+	use super::{
+		compile,
+		TypeName,
+		Type
+	};
+	let mut ns = compile(&mut "struct a { struct b x; DWORD y; HEADER h; };".chars(), &[], target::default()).unwrap();
+	ns.merge(compile(&mut "struct b { DWORD p; WORD q; };".chars(), &[], target::default()).unwrap()).unwrap();
+	assert_eq!(ns.resolve(target::default()), vec![format!("a.h: unknown type HEADER")]);
+	match ns.get(&TypeName::Struct(format!("a"))) {
+		Some(&Type::Struct(ref s)) =>{
+			assert_eq!(s.layout().get("x").unwrap().size, 8);
+			assert_eq!(s.layout().get("y").unwrap().offset, 8);
+			assert_eq!(s.size(), 12)
+		},
+		_ =>panic!("struct a is missing")
+	}
+}
+
+#[test]
+fn test_base_types() {
+	use target;
+	use super::compile;
+	assert_eq!(format!("{:?}", compile(&mut "struct s { ULONG_PTR p; USHORT w; };".chars(), &[], target::default()).unwrap()),
+		format!("struct s {{\n\t{:32} ULONG_PTR p;\n\t{:32} USHORT w;\n\t{:32} (padding);\n}};\n",
+			"00 - 04", "04 - 06", "06 - 08"))
+}
+
+#[test]
+fn test_alignment() {
+	use target;
+	use super::{compile, TypeName};
+	assert_eq!(format!("{:?}", compile(&mut "struct s { BYTE b; struct { DWORD d; BYTE c; }; WORD w; };".chars(), &[], target::default()).unwrap()),
+		format!("struct s {{\n\t{:32} BYTE b;\n\t{:32} (padding);\n\t{:32} DWORD d;\n\t{:32} BYTE c;\n\t{:32} (padding);\n\t{:32} WORD w;\n\t{:32} (padding);\n}};\n",
+			"00 - 01", "01 - 04", "04 - 08", "08 - 09", "09 - 0C", "0C - 0E", "0E - 10"));
+	let mut ns = compile(&mut "typedef GUID UUID;\nstruct t { DWORD d; UUID id; };".chars(), &[], target::default()).unwrap();
+	ns.resolve(target::default());
+	assert_eq!(format!("{:?}", ns.get(&TypeName::Struct(format!("t"))).unwrap()),
+		format!("struct {{\n\t{:32} DWORD d;\n\t{:32} UUID id;\n}}", "00 - 04", "04 - 14"))
+}
+
+#[test]
+fn test_targets() {
+	use super::compile;
+	use target;
+	let code = "struct s { DWORD a; PVOID p; };";
+	assert_eq!(format!("{:?}", compile(&mut code.chars(), &[], target::lookup("x64").unwrap()).unwrap()),
+		format!("struct s {{\n\t{:32} DWORD a;\n\t{:32} (padding);\n\t{:32} PVOID p;\n}};\n",
+			"00 - 04", "04 - 08", "08 - 10"));
+	assert_eq!(format!("{:?}", compile(&mut code.chars(), &[], target::lookup("x86").unwrap()).unwrap()),
+		format!("struct s {{\n\t{:32} DWORD a;\n\t{:32} PVOID p;\n}};\n", "00 - 04", "04 - 08"))
+}
+
+#[test]
+fn test_arrays() {
+	use super::compile;
+	use target;
+	let code = "struct s { WORD w; DWORD d[2][3]; BYTE n[IMAGE_SIZEOF_SHORT_NAME]; BYTE x[(0x10 - 4) / 2 * 2 + 1]; };";
+	assert_eq!(format!("{:?}", compile(&mut code.chars(), &[], target::default()).unwrap()),
+		format!("struct s {{\n\t{:32} WORD w;\n\t{:32} (padding);\n\t{:32} DWORD[2][3] d;\n\t{:32} BYTE[8] n;\n\t{:32} BYTE[13] x;\n\t{:32} (padding);\n}};\n",
+			"00 - 02", "02 - 04", "04 - 1C", "1C - 24", "24 - 31", "31 - 34"))
+}
+
+#[test]
+fn test_comments() {
+	use super::compile;
+	use pre::{preprocess, Macros};
+	use target;
+	let code = "struct s {\n\t// leading\n\tDWORD a; /* in bytes */\n\tWORD b; // reserved\n};";
+	let (code, comments) = preprocess(code, &mut Macros::new()).unwrap();
+	assert_eq!(format!("{:?}", compile(&mut code.chars(), &comments, target::default()).unwrap()),
+		format!("struct s {{\n\t{:32} DWORD a; // leading // in bytes\n\t{:32} WORD b; // reserved\n\t{:32} (padding);\n}};\n",
+			"00 - 04", "04 - 06", "06 - 08"));
+	let code = "struct s {\n#ifdef _WIN64 // 64-bit only\n\t// wide\n\tDWORD64 x;\n#else\n\tDWORD x;\n#endif\n};";
+	let (code, comments) = preprocess(code, &mut Macros::new()).unwrap();
+	assert_eq!(format!("{:?}", compile(&mut code.chars(), &comments, target::default()).unwrap()),
+		format!("struct s {{\n\t{:32} DWORD x;\n}};\n", "00 - 04"))
+}
+
+#[test]
+fn test_bitfields() {
+	use super::compile;
+	use target;
+	let code = "struct s { DWORD a : 3; DWORD : 2; DWORD b : 27; DWORD c : 1; DWORD : 0; DWORD d : 4; WORD e : 4; BYTE f; };";
+	assert_eq!(format!("{:?}", compile(&mut code.chars(), &[], target::default()).unwrap()),
+		format!("struct s {{\n\t{:32} DWORD a: 3;\n\t{:32} DWORD : 2;\n\t{:32} DWORD b: 27;\n\t{:32} DWORD c: 1;\n\t{:32} DWORD d: 4;\n\t{:32} WORD e: 4;\n\t{:32} BYTE f;\n\t{:32} (padding);\n}};\n",
+			"00 - 04 bits 00 - 03", "00 - 04 bits 03 - 05", "00 - 04 bits 05 - 32", "04 - 08 bits 00 - 01",
+			"08 - 0C bits 00 - 04", "0C - 0E bits 00 - 04", "0E - 0F", "0F - 10"))
+}
+
+#[test]
+fn test_pack() {
+	use super::{compile, TypeName};
+	use pre::{preprocess, Macros};
+	use target;
+	let code = "#include <pshpack1.h>\nstruct a { BYTE b; DWORD d; };\n#include <poppack.h>\n\
+		#pragma pack(push, 2)\nstruct c { BYTE b; struct a x; DWORD d; };\n#pragma pack(pop)\n\
+		struct DECLSPEC_ALIGN(16) e { DWORD d; };\nstruct f { BYTE b; struct e x; };\n\
+		#pragma pack(2)\n#pragma pack(show)\nstruct g { BYTE b; DWORD d; };\n#pragma pack()\nstruct h { BYTE b; DWORD d; };\n\
+		#pragma pack(push, 1)\n#pragma pack(pop, 2)\nstruct i { BYTE b; DWORD d; };";
+	let mut macros = Macros::new();
+	for def in target::default().predefined().into_iter() {
+		macros.predefine(def).unwrap()
+	}
+	let mut ns = compile(&mut preprocess(code, &mut macros).unwrap().0.chars(), &[], target::default()).unwrap();
+	assert_eq!(ns.resolve(target::default()), Vec::<String>::new());
+	let sizes = ["a", "c", "e", "f", "g", "h", "i"].iter()
+		.map(|&x| ns.get(&TypeName::Struct(x.to_string())).unwrap().size(target::default()))
+		.collect::<Vec<_>>();
+	assert_eq!(sizes, vec![5, 10, 16, 32, 6, 8, 6])
+}
+
+#[test]
+fn test_enum() {
+	use super::{compile, TypeName};
+	use target;
+	let code = "typedef enum _KIND { A, B = 0x10, C, D = B | 1 << 2, E = -1 } KIND;\n\
+		enum COLOR { RED };\nstruct s { BYTE b; KIND k; enum COLOR c; };";
+	let mut ns = compile(&mut code.chars(), &[], target::default()).unwrap();
+	assert_eq!(ns.resolve(target::default()), Vec::<String>::new());
+	assert_eq!(format!("{:?}", ns.get(&TypeName::Normal(format!("KIND"))).unwrap()),
+		"enum {\n\tA = 0,\n\tB = 16,\n\tC = 17,\n\tD = 20,\n\tE = -1,\n}");
+	assert_eq!(format!("{:?}", ns.get(&TypeName::Struct(format!("s"))).unwrap()),
+		format!("struct {{\n\t{:32} BYTE b;\n\t{:32} (padding);\n\t{:32} KIND k;\n\t{:32} enum COLOR c;\n}}",
+			"00 - 01", "01 - 04", "04 - 08", "08 - 0C"));
+	assert!(compile(&mut "enum e { A = 1 << 64 };".chars(), &[], target::default()).is_err());
+	assert!(compile(&mut "enum e { A = 0x7FFFFFFFFFFFFFFF + 1 };".chars(), &[], target::default()).is_err())
+}
+
+#[test]
+fn test_function_pointers() {
+	use super::{compile, TypeName};
+	use target;
+	let code = "typedef VOID (NTAPI *PIMAGE_TLS_CALLBACK)(PVOID DllHandle, DWORD Reason, PVOID Reserved);\n\
+		struct s { BYTE b; PIMAGE_TLS_CALLBACK tls; VOID (NTAPI *cb)(PVOID); };";
+	let x64 = target::lookup("x64").unwrap();
+	let mut ns = compile(&mut code.chars(), &[], x64).unwrap();
+	assert_eq!(ns.resolve(x64), Vec::<String>::new());
+	assert_eq!(format!("{:?}", ns.get(&TypeName::Struct(format!("s"))).unwrap()),
+		format!("struct {{\n\t{:32} BYTE b;\n\t{:32} (padding);\n\t{:32} PIMAGE_TLS_CALLBACK tls;\n\t{:32} VOID (NTAPI *)(PVOID) cb;\n}}",
+			"00 - 01", "01 - 08", "08 - 10", "10 - 18"))
+}
+
+#[test]
+fn test_prototypes() {
+	use super::{compile, TypeName};
+	use target;
+	let code = "PIMAGE_NT_HEADERS IMAGEAPI ImageNtHeader(\n  _In_ PVOID Base\n);\n\
+		WINBASEAPI BOOL WINAPI ReadFile(_In_ HANDLE hFile, _Out_writes_bytes_(n) LPVOID lpBuffer, [in] DWORD n, _Reserved_ PVOID);";
+	let ns = compile(&mut code.chars(), &[], target::default()).unwrap();
+	assert_eq!(format!("{:?}", ns.get(&TypeName::Function(format!("ImageNtHeader"))).unwrap()),
+		"PIMAGE_NT_HEADERS IMAGEAPI(PVOID Base)");
+	assert_eq!(format!("{:?}", ns.get(&TypeName::Function(format!("ReadFile"))).unwrap()),
+		"BOOL WINAPI(HANDLE hFile, LPVOID lpBuffer, DWORD n, PVOID)")
+}
+
+#[test]
+fn test_qualifiers() {
+	use super::{compile, TypeName};
+	use target;
+	let code = "struct s { unsigned long a; long long int b; signed char c; const volatile DWORD d;\n\
+		char * const e; const wchar_t *f; unsigned g; __unaligned PVOID h; };";
+	let x64 = target::lookup("x64").unwrap();
+	let mut ns = compile(&mut code.chars(), &[], x64).unwrap();
+	assert_eq!(ns.resolve(x64), Vec::<String>::new());
+	assert_eq!(format!("{:?}", ns.get(&TypeName::Struct(format!("s"))).unwrap()),
+		format!("struct {{\n\t{:32} unsigned long a;\n\t{:32} (padding);\n\t{:32} long long b;\n\t{:32} signed char c;\n\
+			\t{:32} (padding);\n\t{:32} const volatile DWORD d;\n\t{:32} char* const e;\n\t{:32} const wchar_t* f;\n\
+			\t{:32} unsigned int g;\n\t{:32} (padding);\n\t{:32} __unaligned PVOID h;\n}}",
+			"00 - 04", "04 - 08", "08 - 10", "10 - 11", "11 - 14", "14 - 18", "18 - 20", "20 - 28",
+			"28 - 2C", "2C - 30", "30 - 38"));
+	assert!(compile(&mut "struct t { unsigned signed x; };".chars(), &[], x64).is_err())
+}
+
+#[test]
+fn test_declarators() {
+	use super::compile;
+	use target;
+	let code = "struct s { WORD MajorVersion, MinorVersion; DWORD a, *b, c[2]; BYTE x : 3, y : 5; };";
+	assert_eq!(format!("{:?}", compile(&mut code.chars(), &[], target::default()).unwrap()),
+		format!("struct s {{\n\t{:32} WORD MajorVersion;\n\t{:32} WORD MinorVersion;\n\t{:32} DWORD a;\n\t{:32} DWORD* b;\n\
+			\t{:32} DWORD[2] c;\n\t{:32} BYTE x: 3;\n\t{:32} BYTE y: 5;\n\t{:32} (padding);\n}};\n",
+			"00 - 02", "02 - 04", "04 - 08", "08 - 0C", "0C - 14", "14 - 15 bits 00 - 03", "14 - 15 bits 03 - 08", "15 - 18"))
+}
+
+#[test]
+fn test_self_reference() {
+	use super::{compile, TypeName};
+	use target;
+	let code = "typedef struct _LIST_ENTRY { struct _LIST_ENTRY *Flink; struct _LIST_ENTRY *Blink; } LIST_ENTRY, *PLIST_ENTRY;\n\
+		struct _A; typedef struct _A *PA; struct _B { PA a; struct _B *b; }; struct _A { struct _B b; PA next; };";
+	let mut ns = compile(&mut code.chars(), &[], target::default()).unwrap();
+	assert_eq!(ns.resolve(target::default()), Vec::<String>::new());
+	assert_eq!(format!("{:?}", ns.get(&TypeName::Normal(format!("PLIST_ENTRY"))).unwrap()), "struct _LIST_ENTRY*");
+	assert_eq!(format!("{:?}", ns.get(&TypeName::Normal(format!("LIST_ENTRY"))).unwrap()),
+		format!("struct {{\n\t{:32} struct _LIST_ENTRY* Flink;\n\t{:32} struct _LIST_ENTRY* Blink;\n}}", "00 - 04", "04 - 08"));
+	assert_eq!(format!("{:?}", ns.get(&TypeName::Struct(format!("_A"))).unwrap()),
+		format!("struct {{\n\t{:32} struct _B b;\n\t{:32} PA next;\n}}", "00 - 08", "08 - 0C"))
+}
+
+#[test]
+fn test_flexible() {
+	use super::compile;
+	use target;
+	let code = "struct a { BYTE b[1]; DWORD Count; WORD Groups[ANYSIZE_ARRAY]; };";
+	assert_eq!(format!("{:?}", compile(&mut code.chars(), &[], target::default()).unwrap()),
+		format!("struct a {{\n\t{:32} BYTE[1] b;\n\t{:32} (padding);\n\t{:32} DWORD Count;\n\t{:32} WORD[1] Groups;\n\t{:32} (padding);\n}};\n",
+			"00 - 01", "01 - 04", "04 - 08", "08 - .. flexible", "0A - 0C"));
+	let mut ns = compile(&mut "struct n { DWORD Length; WCHAR FileName[]; };".chars(), &[], target::default()).unwrap();
+	assert_eq!(ns.resolve(target::default()), Vec::<String>::new());
+	assert_eq!(format!("{:?}", ns),
+		format!("struct n {{\n\t{:32} DWORD Length;\n\t{:32} WCHAR[0] FileName;\n}};\n", "00 - 04", "04 - .. flexible"))
+}
+
+#[test]
+fn test_named_nested() {
+	use super::{compile, TypeName};
+	use target;
+	let code = "struct _IMAGE_SECTION_HEADER { BYTE Name[8]; union { DWORD PhysicalAddress; DWORD VirtualSize; } Misc; DWORD VirtualAddress; };";
+	let mut ns = compile(&mut code.chars(), &[], target::default()).unwrap();
+	assert_eq!(ns.resolve(target::default()), Vec::<String>::new());
+	let value = ns.get(&TypeName::Struct(format!("_IMAGE_SECTION_HEADER"))).unwrap();
+	assert_eq!(format!("{:?}", value),
+		format!("struct {{\n\t{:32} BYTE[8] Name;\n\t{:32} DWORD Misc.PhysicalAddress;\n\t{:32} DWORD Misc.VirtualSize;\n\t{:32} DWORD VirtualAddress;\n}}",
+			"00 - 08", "08 - 0C", "08 - 0C", "0C - 10"));
+	assert_eq!(value.size(target::default()), 16)
+}
+
+#[test]
+fn test_nested_declarators() {
+	use super::{compile, TypeName};
+	use target;
+	let x64 = target::lookup("x64").unwrap();
+	let code = "struct s { struct X { DWORD a; WORD b; } x, *px; union { DWORD d; BYTE c[6]; } u[2], *pu; };";
+	let mut ns = compile(&mut code.chars(), &[], x64).unwrap();
+	assert_eq!(ns.resolve(x64), Vec::<String>::new());
+	assert_eq!(ns.get(&TypeName::Struct(format!("X"))).unwrap().size(x64), 8);
+	assert_eq!(ns.get(&TypeName::Union(format!("s_u"))).unwrap().size(x64), 8);
+	assert_eq!(format!("{:?}", ns.get(&TypeName::Struct(format!("s"))).unwrap()),
+		format!("struct {{\n\t{:32} struct X x;\n\t{:32} struct X* px;\n\t{:32} union s_u[2] u;\n\t{:32} union s_u* pu;\n}}",
+			"00 - 08", "08 - 10", "10 - 20", "20 - 28"));
+	let ns = compile(&mut "typedef struct { struct { DWORD a; } a, *pa; } *PT, T;".chars(), &[], x64).unwrap();
+	assert!(ns.get(&TypeName::Struct(format!("T_a"))).is_some())
+}
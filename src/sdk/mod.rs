@@ -0,0 +1,199 @@
+use target::Target;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Width {
+	Fixed(usize),
+	Pointer,
+	Long
+}
+
+fn width(w: Width, target: &Target)->usize {
+	match w {
+		Width::Fixed(x) =>x,
+		Width::Pointer =>target.pointer_size,
+		Width::Long =>target.long_size
+	}
+}
+
+pub struct BaseType {
+	pub name: &'static str,
+	pub size: Width,
+	pub align: Width,
+	pub signed: bool,
+	pub rust: &'static str
+}
+
+impl BaseType {
+	pub fn size(&self, target: &Target)->usize {
+		width(self.size, target)
+	}
+	
+	pub fn align(&self, target: &Target)->usize {
+		width(self.align, target)
+	}
+	
+	pub fn is_pointer_sized(&self)->bool {
+		self.size == Width::Pointer
+	}
+}
+
+macro_rules! base_types {
+	($($name:ident $size:expr, $align:expr, $signed:expr, $rust:expr;)*) => (
+		static BASE_TYPES: &'static [BaseType] = &[
+			$(BaseType {
+				name: stringify!($name),
+				size: $size,
+				align: $align,
+				signed: $signed,
+				rust: $rust
+			}),*
+		];
+	)
+}
+
+const P: Width = Width::Pointer;
+const L: Width = Width::Long;
+const B0: Width = Width::Fixed(0);
+const B1: Width = Width::Fixed(1);
+const B2: Width = Width::Fixed(2);
+const B4: Width = Width::Fixed(4);
+const B8: Width = Width::Fixed(8);
+const B16: Width = Width::Fixed(16);
+
+base_types! {
+	VOID        B0, B1, false, "::std::os::raw::c_void";
+	BYTE        B1, B1, false, "u8";
+	UCHAR       B1, B1, false, "u8";
+	UINT8       B1, B1, false, "u8";
+	BOOLEAN     B1, B1, false, "u8";
+	CHAR        B1, B1, true,  "i8";
+	CCHAR       B1, B1, true,  "i8";
+	INT8        B1, B1, true,  "i8";
+	WORD        B2, B2, false, "u16";
+	USHORT      B2, B2, false, "u16";
+	UINT16      B2, B2, false, "u16";
+	WCHAR       B2, B2, false, "u16";
+	ATOM        B2, B2, false, "u16";
+	SHORT       B2, B2, true,  "i16";
+	INT16       B2, B2, true,  "i16";
+	DWORD       B4, B4, false, "u32";
+	DWORD32     B4, B4, false, "u32";
+	ULONG       L, L, false, "u32";
+	ULONG32     B4, B4, false, "u32";
+	UINT        B4, B4, false, "u32";
+	UINT32      B4, B4, false, "u32";
+	LCID        B4, B4, false, "u32";
+	COLORREF    B4, B4, false, "u32";
+	ACCESS_MASK B4, B4, false, "u32";
+	LONG        L, L, true,  "i32";
+	LONG32      B4, B4, true,  "i32";
+	INT         B4, B4, true,  "i32";
+	INT32       B4, B4, true,  "i32";
+	BOOL        B4, B4, true,  "i32";
+	NTSTATUS    B4, B4, true,  "i32";
+	HRESULT     B4, B4, true,  "i32";
+	FLOAT       B4, B4, true,  "f32";
+	DWORDLONG   B8, B8, false, "u64";
+	DWORD64     B8, B8, false, "u64";
+	ULONGLONG   B8, B8, false, "u64";
+	ULONG64     B8, B8, false, "u64";
+	UINT64      B8, B8, false, "u64";
+	QWORD       B8, B8, false, "u64";
+	LONGLONG    B8, B8, true,  "i64";
+	LONG64      B8, B8, true,  "i64";
+	INT64       B8, B8, true,  "i64";
+	USN         B8, B8, true,  "i64";
+	DOUBLE      B8, B8, true,  "f64";
+	LARGE_INTEGER  B8, B8, true,  "i64";
+	ULARGE_INTEGER B8, B8, false, "u64";
+	LUID        B8, B4, false, "[u32; 2]";
+	GUID        B16, B4, false, "[u32; 4]";
+	SIZE_T      P, P, false, "usize";
+	ULONG_PTR   P, P, false, "usize";
+	DWORD_PTR   P, P, false, "usize";
+	UINT_PTR    P, P, false, "usize";
+	SSIZE_T     P, P, true,  "isize";
+	LONG_PTR    P, P, true,  "isize";
+	INT_PTR     P, P, true,  "isize";
+	HANDLE      P, P, false, "*mut ::std::os::raw::c_void";
+	HMODULE     P, P, false, "*mut ::std::os::raw::c_void";
+	HINSTANCE   P, P, false, "*mut ::std::os::raw::c_void";
+	HWND        P, P, false, "*mut ::std::os::raw::c_void";
+	HKEY        P, P, false, "*mut ::std::os::raw::c_void";
+	PVOID       P, P, false, "*mut ::std::os::raw::c_void";
+	LPVOID      P, P, false, "*mut ::std::os::raw::c_void";
+	LPCVOID     P, P, false, "*const ::std::os::raw::c_void";
+	PSTR        P, P, false, "*mut i8";
+	LPSTR       P, P, false, "*mut i8";
+	PCSTR       P, P, false, "*const i8";
+	LPCSTR      P, P, false, "*const i8";
+	PWSTR       P, P, false, "*mut u16";
+	LPWSTR      P, P, false, "*mut u16";
+	PCWSTR      P, P, false, "*const u16";
+	LPCWSTR     P, P, false, "*const u16";
+}
+
+static C_TYPES: &'static [(&'static str, &'static str)] = &[
+	("char", "CHAR"),
+	("signed char", "INT8"),
+	("unsigned char", "UCHAR"),
+	("short", "SHORT"),
+	("unsigned short", "USHORT"),
+	("int", "INT"),
+	("unsigned int", "UINT"),
+	("long", "LONG"),
+	("unsigned long", "ULONG"),
+	("long long", "LONGLONG"),
+	("unsigned long long", "ULONGLONG"),
+	("__int8", "INT8"),
+	("unsigned __int8", "UINT8"),
+	("__int16", "INT16"),
+	("unsigned __int16", "UINT16"),
+	("__int32", "INT32"),
+	("unsigned __int32", "UINT32"),
+	("__int64", "LONGLONG"),
+	("unsigned __int64", "ULONGLONG"),
+	("float", "FLOAT"),
+	("double", "DOUBLE"),
+	("long double", "DOUBLE"),
+	("wchar_t", "WCHAR")
+];
+
+pub fn lookup(name: &str)->Option<&'static BaseType> {
+	let name = C_TYPES.iter().find(|&&(x, _)| x == name).map_or(name, |&(_, x)| x);
+	BASE_TYPES.iter().find(|x| x.name == name)
+}
+
+static CONSTANTS: &'static [(&'static str, u64)] = &[
+	("ANYSIZE_ARRAY", 1),
+	("MAX_PATH", 260),
+	("IMAGE_SIZEOF_SHORT_NAME", 8),
+	("IMAGE_NUMBEROF_DIRECTORY_ENTRIES", 16),
+	("IMAGE_SIZEOF_FILE_HEADER", 20),
+	("IMAGE_SIZEOF_SECTION_HEADER", 40),
+	("EXCEPTION_MAXIMUM_PARAMETERS", 15),
+	("MAXIMUM_SUPPORTED_EXTENSION", 512),
+	("SIZE_OF_80387_REGISTERS", 80),
+	("LF_FACESIZE", 32),
+	("CCHDEVICENAME", 32),
+	("MAX_MODULE_NAME32", 255),
+	("NTDDI_WINXP", 0x05010000),
+	("NTDDI_VISTA", 0x06000000),
+	("NTDDI_WIN7", 0x06010000),
+	("NTDDI_WIN8", 0x06020000),
+	("NTDDI_WINBLUE", 0x06030000),
+	("NTDDI_WIN10", 0x0A000000),
+	("_WIN32_WINNT_WINXP", 0x0501),
+	("_WIN32_WINNT_VISTA", 0x0600),
+	("_WIN32_WINNT_WIN7", 0x0601),
+	("_WIN32_WINNT_WIN8", 0x0602),
+	("_WIN32_WINNT_WINBLUE", 0x0603),
+	("_WIN32_WINNT_WIN10", 0x0A00)
+];
+
+pub fn constant(name: &str)->Option<u64> {
+	CONSTANTS.iter().find(|&&(x, _)| x == name).map(|&(_, v)| v)
+}
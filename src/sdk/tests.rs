@@ -0,0 +1,11 @@
+#[test]
+fn test_lookup() {
+	use super::lookup;
+	use target;
+	let x64 = target::lookup("x64").unwrap();
+	assert_eq!(lookup("ULONG").unwrap().size(x64), 4);
+	assert_eq!(lookup("ULONG_PTR").unwrap().size(x64), 8);
+	assert_eq!(lookup("GUID").unwrap().align(x64), 4);
+	assert!(lookup("LONG").unwrap().signed);
+	assert!(lookup("IMAGE_FILE_HEADER").is_none())
+}
@@ -0,0 +1,54 @@
+#[cfg(test)]
+mod tests;
+
+#[derive(PartialEq, Debug)]
+pub struct Target {
+	pub name: &'static str,
+	pub pointer_size: usize,
+	pub long_size: usize,
+	pub max_align: usize,
+	pub defines: &'static [&'static str]
+}
+
+impl Target {
+	pub fn align(&self, natural: usize)->usize {
+		if natural > self.max_align { self.max_align } else { natural }
+	}
+	
+	pub fn predefined(&self)->Vec<&'static str> {
+		COMMON_DEFINES.iter().chain(self.defines.iter()).cloned().collect()
+	}
+}
+
+static COMMON_DEFINES: &'static [&'static str] = &[
+	"_WIN32", "_MSC_VER=1900", "WINVER=0x0A00", "_WIN32_WINNT=0x0A00", "NTDDI_VERSION=0x0A000000",
+	"DECLSPEC_ALIGN(x)=__declspec(align(x))"
+];
+
+static TARGETS: &'static [Target] = &[
+	Target { name: "x86", pointer_size: 4, long_size: 4, max_align: 8,
+		defines: &["_X86_", "_M_IX86=600"] },
+	Target { name: "x64", pointer_size: 8, long_size: 4, max_align: 8,
+		defines: &["_WIN64", "_AMD64_", "_M_AMD64=100", "_M_X64=100"] },
+	Target { name: "arm64", pointer_size: 8, long_size: 4, max_align: 8,
+		defines: &["_WIN64", "_ARM64_", "_M_ARM64=1"] }
+];
+
+pub fn lookup(name: &str)->Option<&'static Target> {
+	let name = name.to_lowercase();
+	let name = match &*name {
+		"i386" | "win32" =>"x86",
+		"amd64" | "x86_64" | "win64" =>"x64",
+		"aarch64" =>"arm64",
+		x =>x
+	};
+	TARGETS.iter().find(|x| x.name == name)
+}
+
+pub fn names()->Vec<&'static str> {
+	TARGETS.iter().map(|x| x.name).collect()
+}
+
+pub fn default()->&'static Target {
+	&TARGETS[0]
+}
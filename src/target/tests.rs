@@ -0,0 +1,9 @@
+#[test]
+fn test_lookup() {
+	use super::{lookup, default, names};
+	assert_eq!(lookup("AMD64").unwrap().pointer_size, 8);
+	assert_eq!(lookup("arm64").unwrap().long_size, 4);
+	assert_eq!(default().name, "x86");
+	assert!(lookup("mips").is_none());
+	assert_eq!(names(), vec!["x86", "x64", "arm64"])
+}
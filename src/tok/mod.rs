@@ -1,3 +1,6 @@
+use std::mem::replace;
+use pre::Comment;
+
 #[cfg(test)]
 mod tests;
 
@@ -14,30 +17,64 @@ pub enum Token {
 	LeftBrace,
 	RightBrace,
 	Typedef,
-	Pointer
+	Pointer,
+	Number(u64),
+	LeftBracket,
+	RightBracket,
+	LeftParen,
+	RightParen,
+	Plus,
+	Minus,
+	Slash,
+	Colon,
+	Enum,
+	Equal,
+	Or,
+	ShiftLeft
+}
+
+pub fn parse_number(s: &str)->Result<u64, String> {
+	let digits = s.trim_right_matches(|c| c == 'u' || c == 'U' || c == 'l' || c == 'L');
+	let (digits, radix) = if digits.starts_with("0x") || digits.starts_with("0X") {
+		(&digits[2 ..], 16)
+	} else if digits.len() > 1 && digits.starts_with("0") {
+		(&digits[1 ..], 8)
+	} else {
+		(digits, 10)
+	};
+	u64::from_str_radix(digits, radix).map_err(|_| format!("illegal number {}", s))
 }
 
 impl Token {
 	pub fn parse(reader: &mut Iterator<Item=char>)->Result<Vec<Token>, String> {
+		Token::parse_lines(reader).map(|x| x.into_iter().map(|(tok, _)| tok).collect())
+	}
+	
+	// every token comes with the line it starts on
+	pub fn parse_lines(reader: &mut Iterator<Item=char>)->Result<Vec<(Token, usize)>, String> {
 		let mut ret = Vec::new();
 		let mut elem = String::new();
+		let mut line = 1;
+		let reader = &mut reader.peekable();
 		
 		macro_rules! append_word{
 			() => (
 				if !elem.is_empty() {
 					let first = elem.as_bytes()[0];
-					if '0' as u8 <= first && '9' as u8 >= first {
-						return Err(format!("unexpected character {:?}", first as char))
-					}
-					ret.push(match &*elem {
-						"struct" =>Token::Struct,
-						"union" =>Token::Union,
-						"DWORD" =>Token::DWORD,
-						"WORD" =>Token::WORD,
-						"BYTE" =>Token::BYTE,
-						"typedef" =>Token::Typedef,
-						_ =>Token::Ident(elem.clone())
-					});
+					ret.push((if '0' as u8 <= first && '9' as u8 >= first {
+						Token::Number(try!(parse_number(&elem)))
+					} else {
+						match &*elem {
+							"struct" =>Token::Struct,
+							"union" =>Token::Union,
+							"enum" =>Token::Enum,
+							"DWORD" =>Token::DWORD,
+							"WORD" =>Token::WORD,
+							"BYTE" =>Token::BYTE,
+							"typedef" =>Token::Typedef,
+							_ =>Token::Ident(elem.clone())
+						}
+					}, line));
 					elem.clear()
 				}
 			)
@@ -45,28 +82,77 @@ impl Token {
 				
 		while let Some(c) = reader.next() {
 			match c {
-				' ' | '\t' | '\r' | '\n' =>{
+				' ' | '\t' | '\r' =>{
 					append_word!();
 				},
+				'\n' =>{
+					append_word!();
+					line += 1
+				},
 				'*' =>{
 					append_word!();
-					ret.push(Token::Pointer)
+					ret.push((Token::Pointer, line))
 				},
 				';' =>{
 					append_word!();
-					ret.push(Token::SemiColon)
+					ret.push((Token::SemiColon, line))
 				},
 				',' =>{
 					append_word!();
-					ret.push(Token::Comma)
+					ret.push((Token::Comma, line))
 				},
 				'{' =>{
 					append_word!();
-					ret.push(Token::LeftBrace)
+					ret.push((Token::LeftBrace, line))
 				},
 				'}' =>{
 					append_word!();
-					ret.push(Token::RightBrace)
+					ret.push((Token::RightBrace, line))
+				},
+				'[' =>{
+					append_word!();
+					ret.push((Token::LeftBracket, line))
+				},
+				']' =>{
+					append_word!();
+					ret.push((Token::RightBracket, line))
+				},
+				'(' =>{
+					append_word!();
+					ret.push((Token::LeftParen, line))
+				},
+				')' =>{
+					append_word!();
+					ret.push((Token::RightParen, line))
+				},
+				'=' =>{
+					append_word!();
+					ret.push((Token::Equal, line))
+				},
+				'|' =>{
+					append_word!();
+					ret.push((Token::Or, line))
+				},
+				'<' if reader.peek() == Some(&'<') =>{
+					append_word!();
+					reader.next().unwrap();
+					ret.push((Token::ShiftLeft, line))
+				},
+				':' =>{
+					append_word!();
+					ret.push((Token::Colon, line))
+				},
+				'+' =>{
+					append_word!();
+					ret.push((Token::Plus, line))
+				},
+				'-' =>{
+					append_word!();
+					ret.push((Token::Minus, line))
+				},
+				'/' =>{
+					append_word!();
+					ret.push((Token::Slash, line))
 				},
 				'_' | '0' ... '9' | 'a' ... 'z' | 'A' ... 'Z' =>elem.push(c),
 				_ =>return Err(format!("unexpected character {:?}", c))
@@ -78,7 +164,7 @@ impl Token {
 }
 
 #[derive(Clone, Debug)]
-pub struct TokenStream(Vec<Token>);
+pub struct TokenStream(Vec<Token>, Vec<Vec<(String, bool)>>);
 
 impl TokenStream {
 	pub fn peek(&mut self)->Option<Token> {
@@ -89,16 +175,45 @@ impl TokenStream {
 		}
 	}
 	
+	pub fn peek_at(&mut self, n: usize)->Option<Token> {
+		if self.0.len() <= n {
+			None
+		} else {
+			Some(self.0[n].clone())
+		}
+	}
+	
 	pub fn read(&mut self)->Option<Token> {
 		if self.0.is_empty() {
 			None
 		} else {
+			self.1.remove(0);
 			Some(self.0.remove(0))
 		}
 	}
 	
+	pub fn comments(&mut self, trailing: bool)->Vec<String> {
+		let (ret, rest): (Vec<_>, Vec<_>) = replace(&mut self.1[0], Vec::new())
+			.into_iter().partition(|&(_, x)| x == trailing);
+		self.1[0] = rest;
+		ret.into_iter().map(|(text, _)| text).collect()
+	}
+	
 	pub fn new(v: Vec<Token>)->TokenStream {
-		TokenStream(v)
+		let comments = v.iter().map(|_| Vec::new()).chain(Some(Vec::new()).into_iter()).collect();
+		TokenStream(v, comments)
+	}
+	
+	// a comment goes before the first token of a later line when it trails code, before the first token
+	// from its own line on otherwise
+	pub fn with_comments(v: Vec<(Token, usize)>, comments: &[Comment])->TokenStream {
+		let mut slots = v.iter().map(|_| Vec::new()).chain(Some(Vec::new()).into_iter()).collect::<Vec<_>>();
+		for comment in comments.iter() {
+			let idx = v.iter().position(|&(_, line)| line > comment.line || (line == comment.line && !comment.trailing))
+				.unwrap_or(v.len());
+			slots[idx].push((comment.text.clone(), comment.trailing))
+		}
+		TokenStream(v.into_iter().map(|(tok, _)| tok).collect(), slots)
 	}
 	
 	pub fn eat(&mut self, tok: Token)->Result<(), String> {
@@ -10,3 +10,37 @@ fn test_simple_case_for_tokenizer() {
 			Token::Ident(format!("u32")),
 			Token::SemiColon])
 }
+
+#[test]
+fn test_numbers() {
+	use super::Token;
+	let rslt = Token::parse(&mut "[0x1F + 010 - 3UL]".chars()).unwrap();
+	assert_eq!(rslt,
+		vec![
+			Token::LeftBracket,
+			Token::Number(31),
+			Token::Plus,
+			Token::Number(8),
+			Token::Minus,
+			Token::Number(3),
+			Token::RightBracket])
+}
+
+#[test]
+fn test_comments() {
+	use super::{Token, TokenStream};
+	use pre::Comment;
+	let rslt = Token::parse_lines(&mut "\nDWORD a;\nWORD b;".chars()).unwrap();
+	assert_eq!(rslt.iter().map(|x| x.1).collect::<Vec<_>>(), vec![2, 2, 2, 3, 3, 3]);
+	let comments = vec![
+		Comment { line: 1, text: format!("size"), trailing: false },
+		Comment { line: 2, text: format!("in bytes"), trailing: true }];
+	let mut stream = TokenStream::with_comments(rslt, &comments);
+	assert_eq!(stream.comments(true), Vec::<String>::new());
+	assert_eq!(stream.comments(false), vec![format!("size")]);
+	assert_eq!(stream.read(), Some(Token::DWORD));
+	stream.read().unwrap();
+	stream.read().unwrap();
+	assert_eq!(stream.comments(true), vec![format!("in bytes")]);
+	assert_eq!(stream.read(), Some(Token::WORD))
+}
@@ -82,6 +82,11 @@ pub fn find_code_blocks(html: &str)->Vec<String> {
 	})
 }
 
+pub fn find_library(html: &str)->Option<String> {
+	let re = Regex::new(r"(?i)Library(?:\s*</[a-z]+>)*\s*<t[dh][^>]*>(?:\s*<[^>]+>)*\s*([A-Za-z0-9_]+)\.lib").unwrap();
+	re.captures(html).and_then(|caps| caps.at(1)).map(|x| x.to_lowercase())
+}
+
 pub fn decode(page: &str)->Result<String, String> {
 	let mut entity = None;
 	let mut inside_tag = false;
@@ -29,3 +29,11 @@ fn test_cache() {
 	let rslt = load_from_cache("test").unwrap();
 	assert_eq!(rslt, "test")
 }
+
+#[test]
+fn test_find_library() {
+	use web::find_library;
+	let html = "<h2>Requirements</h2><table><tr><th scope=\"row\">Library</th>\n<td><dl><dt>Dbghelp.lib</dt></dl></td></tr></table>";
+	assert_eq!(find_library(html), Some(format!("dbghelp")));
+	assert_eq!(find_library("<td>DLL</td><td>Kernel32.dll</td>"), None)
+}